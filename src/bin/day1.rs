@@ -28,17 +28,10 @@ fn main() {
         77867, 64235, 141137, 102636,
     ];
 
-    println!(
-        "{}",
-        inputs.iter().map(calculate_fuel).into_iter().sum::<i32>()
-    );
+    println!("{}", inputs.iter().map(calculate_fuel).sum::<i32>());
 
     println!(
         "{}",
-        inputs
-            .iter()
-            .map(calculate_fuel_advanced)
-            .into_iter()
-            .sum::<i32>()
+        inputs.iter().map(calculate_fuel_advanced).sum::<i32>()
     );
 }
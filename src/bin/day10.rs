@@ -77,16 +77,13 @@ fn main() {
     ];
 
     let mut asteroids: HashSet<AsteroidLocation> = HashSet::new();
-    let mut line_count = 0;
 
-    for line in input {
+    for (line_count, line) in input.iter().enumerate() {
         for (x, value) in line.chars().enumerate() {
             if value == '#' {
-                asteroids.insert((x as i64, line_count));
+                asteroids.insert((x as i64, line_count as i64));
             }
         }
-
-        line_count += 1;
     }
 
     let mut best_asteroid_count = 0;
@@ -114,7 +111,7 @@ fn main() {
     println!("{}, {:?}", best_asteroid_count, best_asteroid_position);
 
     // Part 2
-    let prime_asteroid = *(asteroids.get(&best_asteroid_position).unwrap());
+    let prime_asteroid = *(asteroids.get(best_asteroid_position).unwrap());
 
     let mut target_count = 0;
 
@@ -139,9 +136,7 @@ fn main() {
 
         let rounded_angle = (angle * 10000000.0) as i64;
 
-        if !nearest_asteroid_structure.contains_key(&rounded_angle) {
-            nearest_asteroid_structure.insert(rounded_angle, SliceDeque::<AsteroidLocation>::new());
-        };
+        nearest_asteroid_structure.entry(rounded_angle).or_default();
 
         nearest_asteroid_structure
             .get_mut(&rounded_angle)
@@ -155,15 +150,15 @@ fn main() {
                 if calculate_distance(first, &prime_asteroid)
                     < calculate_distance(second, &prime_asteroid)
                 {
-                    return Ordering::Less;
+                    Ordering::Less
                 } else {
-                    return Ordering::Greater;
+                    Ordering::Greater
                 }
             })
     }
 
     loop {
-        for (_angle, distances) in &mut nearest_asteroid_structure {
+        for distances in nearest_asteroid_structure.values_mut() {
             if distances.is_empty() {
                 continue;
             }
@@ -46,8 +46,8 @@ fn update_grid(directions: &Vec<&str>) -> HashMap<(i32, i32), i32> {
 }
 
 fn main() {
-    let wire_one_directions = WIRE_ONE.split(",").into_iter().collect::<Vec<&str>>();
-    let wire_two_directions = WIRE_TWO.split(",").into_iter().collect::<Vec<&str>>();
+    let wire_one_directions = WIRE_ONE.split(",").collect::<Vec<&str>>();
+    let wire_two_directions = WIRE_TWO.split(",").collect::<Vec<&str>>();
 
     let grid1 = update_grid(&wire_one_directions);
     let grid2 = update_grid(&wire_two_directions);
@@ -68,7 +68,7 @@ fn main() {
         min_manhattan = i32::min(min_manhattan, intersection.0.abs() + intersection.1.abs());
         min_steps = i32::min(
             min_steps,
-            grid1.get(&intersection).unwrap() + grid2.get(&intersection).unwrap(),
+            grid1.get(intersection).unwrap() + grid2.get(intersection).unwrap(),
         );
     }
 
@@ -20,9 +20,9 @@ fn digit_count(code: i32, strict: bool) -> bool {
     let counter = str_code.chars().collect::<Counter<_>>();
 
     if strict {
-        return counter.values().filter(|&&n| n == 2).count() > 0;
+        counter.values().filter(|&&n| n == 2).count() > 0
     } else {
-        return counter.values().filter(|&&n| n > 1).count() > 0;
+        counter.values().filter(|&&n| n > 1).count() > 0
     }
 }
 
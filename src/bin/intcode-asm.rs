@@ -0,0 +1,33 @@
+use std::env;
+use std::fs;
+use std::process;
+use AdventOfCode2019::intcode::asm::assemble;
+use AdventOfCode2019::intcode::{InstructionSet, Program};
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: intcode-asm <source file>");
+            process::exit(1);
+        }
+    };
+
+    let source = match fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("{}: {}", path, error);
+            process::exit(1);
+        }
+    };
+
+    let instructions: InstructionSet = InstructionSet::standard();
+
+    match assemble(&instructions, &source) {
+        Ok(program) => println!("{}", Program::from(program)),
+        Err(error) => {
+            eprintln!("{}:{}: {}", path, error.line, error.message);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use std::env;
+use std::process;
+use std::time::{Duration, Instant};
+use AdventOfCode2019::intcode::asm::assemble;
+use AdventOfCode2019::intcode::{InstructionSet, IntCodeInterpreter, ParameterMode, RegisterSize};
+
+const BRANCHES: usize = 2_000;
+const DECODE_RUNS: usize = 20;
+const PAGE_BYTES: usize = 1024 * 8;
+
+fn time<F: FnOnce() -> T, T>(f: F) -> (T, Duration) {
+    let start = Instant::now();
+    let result = f();
+    (result, start.elapsed())
+}
+
+// Sums its inputs into a cell near the top of a program padded out to 64k cells, which is about
+// the size of the bigger puzzle programs once they've been running a while. Before that it runs
+// almost all of those cells as straight-line setup code so the decode cache is full when it's
+// forked, then patches its first jump so that a fresh copy starting from 0 goes straight to the loop
+fn branching_program() -> Vec<RegisterSize> {
+    // Jump to the setup code at 14, past the loop at 3
+    let mut program = vec![1105, 1, 14];
+    program.extend(&[3, 60000, 1, 60000, 60001, 60001, 4, 60001, 1105, 1, 3]);
+
+    // Leave room for the patch and the jump back before the sums at 60000
+    while program.len() + 4 + 3 <= 60000 {
+        program.extend(&[1101, 0, 0, 65535]);
+    }
+
+    program.extend(&[1101, 1106, 0, 0, 1105, 1, 3]);
+    program.extend((program.len()..65536).map(|address| address as RegisterSize));
+    program
+}
+
+// Returns how many pages the branches needed between them, over and above the parent's
+fn explore<F: Fn(&IntCodeInterpreter) -> IntCodeInterpreter>(
+    parent: &IntCodeInterpreter,
+    branch: F,
+) -> usize {
+    (0..BRANCHES)
+        .map(|direction| {
+            let mut child = branch(parent);
+            child.add_input(direction as RegisterSize);
+            child.run().unwrap();
+            child.memory().allocated_pages() - child.memory().shared_pages()
+        })
+        .sum()
+}
+
+fn bench_fork() {
+    let mut parent = IntCodeInterpreter::new();
+    parent.reset(&branching_program());
+    parent.run().unwrap();
+
+    // What cloning cost before memory was paged: a full copy of every cell
+    let (copy_pages, copy_time) = time(|| {
+        explore(&parent, |parent| {
+            let mut child = IntCodeInterpreter::new();
+            child.reset(&parent.memory().to_vec());
+            child
+        })
+    });
+
+    let (fork_pages, fork_time) = time(|| explore(&parent, IntCodeInterpreter::fork));
+
+    println!(
+        "fork: {} branches of a 64k cell program, pages each branch had to allocate",
+        BRANCHES
+    );
+    println!(
+        "  deep copy: {:>10.2?} {:>8} pages {:>6} MiB",
+        copy_time,
+        copy_pages,
+        (copy_pages * PAGE_BYTES) >> 20
+    );
+    println!(
+        "  fork:      {:>10.2?} {:>8} pages {:>6} MiB",
+        fork_time,
+        fork_pages,
+        (fork_pages * PAGE_BYTES) >> 20
+    );
+}
+
+// Adds up i * j over a 300x300 grid, which is about 450,000 instructions of mixed modes
+const NESTED_LOOPS: &str = "
+            ARB scratch
+    outer:  ADD [i], -1, [i]
+            ADD 0, 300, [j]
+    inner:  MUL [i], [j], [rb]
+            ADD [rb], [total], [total]
+            ADD [j], -1, [j]
+            LT 0, [j], [more]
+            JNZ [more], inner
+            JNZ [i], outer
+            OUT [total]
+            HLT
+    i:      .data 301
+    j:      .data 0
+    more:   .data 0
+    total:  .data 0
+    scratch: .zero 1
+";
+
+// The interpreter loop as it was before decoding moved to arithmetic and a cache, kept here as
+// the baseline. Returns the outputs and how many instructions ran
+fn legacy_run(program: &[RegisterSize]) -> (Vec<RegisterSize>, usize) {
+    let mut memory = program.to_vec();
+    let mut outputs = Vec::new();
+    let mut instruction_pointer = 0;
+    let mut relative_offset = 0;
+    let mut instructions = 0;
+
+    loop {
+        let opcode = memory[instruction_pointer];
+        instructions += 1;
+
+        // Kind of gross, but prepend with a bunch of zeroes in case we need a default
+        let mut opcode_string = "00000000000".to_owned() + opcode.to_string().as_str();
+        opcode_string.pop();
+        opcode_string.pop();
+
+        let parameter_modes = opcode_string
+            .chars()
+            .rev()
+            .map(|n| match n {
+                '0' => ParameterMode::PositionMode,
+                '1' => ParameterMode::ImmediateMode,
+                '2' => ParameterMode::RelativeMode,
+                _ => panic!("Unknown parameter mode"),
+            })
+            .collect::<Vec<ParameterMode>>();
+
+        let address = |offset: usize, relative_offset: RegisterSize| {
+            let target = memory[instruction_pointer + offset];
+
+            match parameter_modes[offset - 1] {
+                ParameterMode::PositionMode => target as usize,
+                ParameterMode::ImmediateMode => instruction_pointer + offset,
+                ParameterMode::RelativeMode => (target + relative_offset) as usize,
+            }
+        };
+
+        let a = address(1, relative_offset);
+        let b = address(2, relative_offset);
+        let c = address(3, relative_offset);
+
+        match opcode % 100 {
+            1 => {
+                memory[c] = memory[a] + memory[b];
+                instruction_pointer += 4;
+            }
+            2 => {
+                memory[c] = memory[a] * memory[b];
+                instruction_pointer += 4;
+            }
+            4 => {
+                outputs.push(memory[a]);
+                instruction_pointer += 2;
+            }
+            5 if memory[a] != 0 => instruction_pointer = memory[b] as usize,
+            6 if memory[a] == 0 => instruction_pointer = memory[b] as usize,
+            5 | 6 => instruction_pointer += 3,
+            7 => {
+                memory[c] = (memory[a] < memory[b]) as RegisterSize;
+                instruction_pointer += 4;
+            }
+            8 => {
+                memory[c] = (memory[a] == memory[b]) as RegisterSize;
+                instruction_pointer += 4;
+            }
+            9 => {
+                relative_offset += memory[a];
+                instruction_pointer += 2;
+            }
+            99 => return (outputs, instructions),
+            _ => panic!("Unsupported opcode {}", opcode),
+        }
+    }
+}
+
+fn bench_decode() {
+    let instructions: InstructionSet = InstructionSet::standard();
+    let mut program = assemble(&instructions, NESTED_LOOPS).unwrap();
+    // The legacy loop resolves all three operand addresses up front, so give it room to overrun
+    program.extend(&[0, 0, 0]);
+
+    let ((legacy_outputs, instructions), legacy_time) = time(|| {
+        let mut result = (Vec::new(), 0);
+
+        for _ in 0..DECODE_RUNS {
+            result = legacy_run(&program);
+        }
+
+        result
+    });
+
+    let (outputs, time) = time(|| {
+        let mut interpreter = IntCodeInterpreter::new();
+
+        for _ in 0..DECODE_RUNS {
+            interpreter.reset(&program);
+            interpreter.run().unwrap();
+        }
+
+        interpreter.drain_outputs()
+    });
+
+    assert_eq!(outputs, legacy_outputs);
+
+    let total = (instructions * DECODE_RUNS) as f64;
+
+    println!(
+        "decode: {} runs of {} instructions",
+        DECODE_RUNS, instructions
+    );
+    println!(
+        "  string decoding: {:>10.2?} {:>12.0} instructions/s",
+        legacy_time,
+        total / legacy_time.as_secs_f64()
+    );
+    println!(
+        "  decode cache:    {:>10.2?} {:>12.0} instructions/s",
+        time,
+        total / time.as_secs_f64()
+    );
+}
+
+fn main() {
+    let benchmarks: [(&str, fn()); 2] = [("fork", bench_fork), ("decode", bench_decode)];
+    let selected = env::args().nth(1);
+
+    if let Some(name) = &selected {
+        if !benchmarks.iter().any(|(benchmark, _)| benchmark == name) {
+            eprintln!("Usage: intcode-bench [fork|decode]");
+            process::exit(1);
+        }
+    }
+
+    for (name, benchmark) in benchmarks.iter() {
+        if selected.as_ref().is_none_or(|selected| selected == name) {
+            benchmark();
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::process;
+use AdventOfCode2019::intcode::debugger::{Command, Debugger};
+use AdventOfCode2019::intcode::Program;
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: intcode-dbg <program file>");
+            process::exit(1);
+        }
+    };
+
+    let program: Program = match Program::from_file(&path) {
+        Ok(program) => program,
+        Err(error) => {
+            eprintln!("{}: {}", path, error);
+            process::exit(1);
+        }
+    };
+
+    let mut debugger = Debugger::new(&program);
+    let mut last_command: Option<Command> = None;
+    let stdin = io::stdin();
+
+    loop {
+        print!("(dbg) ");
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+
+        // An empty line repeats the last command, which makes stepping less tedious
+        let command = if line.trim().is_empty() {
+            match &last_command {
+                Some(command) => command.clone(),
+                None => continue,
+            }
+        } else {
+            match Command::parse(&line, debugger.interpreter().instruction_set()) {
+                Ok(command) => command,
+                Err(error) => {
+                    println!("{}", error);
+                    continue;
+                }
+            }
+        };
+
+        if command == Command::Quit {
+            break;
+        }
+
+        println!("{}", debugger.execute(&command));
+        last_command = Some(command);
+    }
+}
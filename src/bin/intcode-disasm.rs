@@ -0,0 +1,62 @@
+use std::env;
+use std::io;
+use std::process;
+use AdventOfCode2019::intcode::disasm::disassemble;
+use AdventOfCode2019::intcode::{Coverage, InstructionSet, IsaLevel, Program};
+
+fn usage() -> ! {
+    eprintln!(
+        "Usage: intcode-disasm [--coverage <file>] [--isa day2|day5|day9] \
+         <program file, or - for stdin>"
+    );
+    process::exit(1);
+}
+
+fn main() {
+    let mut program_path = None;
+    let mut coverage_path = None;
+    let mut isa_level = IsaLevel::default();
+
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--coverage" => coverage_path = Some(args.next().unwrap_or_else(|| usage())),
+            "--isa" => match args.next().map(|level| level.parse()) {
+                Some(Ok(level)) => isa_level = level,
+                _ => usage(),
+            },
+            _ if program_path.is_none() => program_path = Some(arg),
+            _ => usage(),
+        }
+    }
+
+    let path = program_path.unwrap_or_else(|| usage());
+
+    let loaded = if path == "-" {
+        Program::from_reader(io::stdin())
+    } else {
+        Program::from_file(&path)
+    };
+
+    let program: Program = match loaded {
+        Ok(program) => program,
+        Err(error) => {
+            eprintln!("{}: {}", path, error);
+            process::exit(1);
+        }
+    };
+
+    let instructions: InstructionSet = InstructionSet::for_level(isa_level);
+
+    match coverage_path {
+        Some(coverage_path) => match Coverage::load(&coverage_path) {
+            Ok(coverage) => print!("{}", coverage.report(&instructions, &program)),
+            Err(error) => {
+                eprintln!("{}: {}", coverage_path, error);
+                process::exit(1);
+            }
+        },
+        None => print!("{}", disassemble(&instructions, &program)),
+    }
+}
@@ -0,0 +1,177 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+use std::time::Duration;
+use AdventOfCode2019::intcode::{
+    Coverage, IntCodeInterpreter, IsaLevel, Limits, Program, RunState, Snapshot, StdinInput,
+    StdoutOutput,
+};
+
+const USAGE: &str = "Usage: intcode-run [--resume <snapshot>] [--save <snapshot>] \
+                     [--max-instructions <count>] [--timeout <seconds>] [--detect-loops] \
+                     [--profile] [--profile-csv <file>] [--coverage <file>] \
+                     [--isa day2|day5|day9] [program file]";
+
+// How many of the busiest opcodes, addresses and cells the profile report shows
+const PROFILE_ENTRIES: usize = 20;
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn main() {
+    let mut program_path = None;
+    let mut resume_path = None;
+    let mut save_path = None;
+    let mut limits = Limits::new();
+    let mut detect_loops = false;
+    let mut profile = false;
+    let mut profile_csv_path = None;
+    let mut coverage_path = None;
+    let mut isa_level: Option<IsaLevel> = None;
+
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--resume" => resume_path = Some(args.next().unwrap_or_else(|| usage())),
+            "--save" => save_path = Some(args.next().unwrap_or_else(|| usage())),
+            "--max-instructions" => match args.next().map(|count| count.parse()) {
+                Some(Ok(count)) => limits = limits.instructions(count),
+                _ => usage(),
+            },
+            "--timeout" => match args.next().map(|seconds| seconds.parse()) {
+                Some(Ok(seconds)) => match Duration::try_from_secs_f64(seconds) {
+                    Ok(duration) => limits = limits.duration(duration),
+                    Err(_) => usage(),
+                },
+                _ => usage(),
+            },
+            "--detect-loops" => detect_loops = true,
+            "--profile" => profile = true,
+            "--profile-csv" => profile_csv_path = Some(args.next().unwrap_or_else(|| usage())),
+            "--coverage" => coverage_path = Some(args.next().unwrap_or_else(|| usage())),
+            "--isa" => match args.next().map(|level| level.parse()) {
+                Some(Ok(level)) => isa_level = Some(level),
+                _ => usage(),
+            },
+            _ if program_path.is_none() => program_path = Some(arg),
+            _ => usage(),
+        }
+    }
+
+    let mut interpreter = IntCodeInterpreter::new();
+
+    // Snapshots bring their own instruction set level, which --isa can only confirm
+    match (&resume_path, &program_path) {
+        (Some(_), Some(_)) | (None, None) => usage(),
+        (Some(path), None) => match Snapshot::load(path) {
+            Ok(snapshot) => match (snapshot.isa_level, isa_level) {
+                (Some(saved), Some(level)) if saved != level => {
+                    eprintln!("{}: saved with --isa {}, not {}", path, saved, level);
+                    process::exit(1);
+                }
+                _ => interpreter.restore(&snapshot),
+            },
+            Err(error) => {
+                eprintln!("{}: {}", path, error);
+                process::exit(1);
+            }
+        },
+        (None, Some(path)) => match Program::from_file(path) {
+            Ok(program) => {
+                interpreter.set_isa_level(isa_level.unwrap_or_default());
+                interpreter.reset(&program);
+            }
+            Err(error) => {
+                eprintln!("{}: {}", path, error);
+                process::exit(1);
+            }
+        },
+    }
+
+    interpreter.set_input_source(StdinInput::new());
+    interpreter.set_output_sink(StdoutOutput);
+    interpreter.set_limits(limits);
+    interpreter.set_loop_detection(detect_loops);
+    interpreter.set_profiling(profile || profile_csv_path.is_some());
+    interpreter.set_coverage(coverage_path.is_some());
+
+    // A run that fails still gets its profile, coverage and snapshot written before exiting
+    let result = interpreter.run();
+
+    // Everything has already gone to stdout, so there's no point carrying it into the snapshot
+    interpreter.clear_output();
+
+    if let Some(counts) = interpreter.profile() {
+        if profile {
+            eprint!(
+                "{}",
+                counts.report(interpreter.instruction_set(), PROFILE_ENTRIES)
+            );
+        }
+
+        if let Some(path) = &profile_csv_path {
+            if let Err(error) = fs::write(path, counts.to_csv(interpreter.instruction_set())) {
+                eprintln!("{}: {}", path, error);
+                process::exit(1);
+            }
+        }
+    }
+
+    // Coverage accumulates across runs, so each one adds to whatever's already in the file
+    if let (Some(path), Some(mut coverage)) = (&coverage_path, interpreter.take_coverage()) {
+        let saved = match Coverage::load(path) {
+            Ok(previous) => {
+                coverage.merge(&previous);
+                coverage.save(path)
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => coverage.save(path),
+            Err(error) => Err(error),
+        };
+
+        if let Err(error) = saved {
+            eprintln!("{}: {}", path, error);
+            process::exit(1);
+        }
+    }
+
+    if let Some(path) = &save_path {
+        if let Err(error) = interpreter.snapshot().save(path) {
+            eprintln!("{}: {}", path, error);
+            process::exit(1);
+        }
+    }
+
+    let state = match result {
+        Ok(state) => state,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    };
+
+    match state {
+        RunState::AwaitingInput => {
+            eprintln!("Waiting for input at {}", interpreter.instruction_pointer())
+        }
+        RunState::LimitExceeded(limit) => {
+            eprintln!(
+                "Stopped at {}: {}",
+                interpreter.instruction_pointer(),
+                limit
+            );
+            process::exit(2);
+        }
+        RunState::LoopDetected(info) => {
+            eprintln!(
+                "Stuck in a loop between {} and {}, repeating every {} instructions",
+                info.start, info.end, info.period
+            );
+            process::exit(2);
+        }
+        RunState::Halted => {}
+    }
+}
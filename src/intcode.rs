@@ -1,7 +1,50 @@
-use std::str::FromStr;
-use text_io::*;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
 
-#[derive(Copy, Clone, Eq, PartialEq)]
+pub mod ascii;
+pub mod asm;
+mod coverage;
+pub mod debugger;
+pub mod disasm;
+pub mod expect;
+mod instructions;
+mod io;
+mod limits;
+mod loops;
+mod memory;
+pub mod network;
+mod profile;
+mod program;
+mod register;
+pub mod snapshot;
+#[cfg(test)]
+mod test_programs;
+pub mod threaded;
+pub mod trace;
+mod varint;
+
+pub use coverage::Coverage;
+use instructions::MAX_PARAMETERS;
+pub use instructions::{Execution, InstructionSet, IsaLevel, Operation};
+pub use io::{
+    ChannelInput, ChannelOutput, FileInput, FileOutput, FnInput, FnOutput, InputSource, OutputSink,
+    QueueInput, QueueOutput, StdinInput, StdoutOutput,
+};
+use limits::Progress;
+pub use limits::{Limit, Limits};
+use loops::LoopDetector;
+pub use loops::LoopInfo;
+pub use memory::Memory;
+pub use profile::Profile;
+pub use program::{Program, ProgramError};
+pub use register::{ArithmeticPolicy, Register};
+pub use snapshot::Snapshot;
+pub use trace::{TraceEvent, TraceRecorder, Tracer};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum ParameterMode {
     PositionMode,
     ImmediateMode,
@@ -10,243 +53,919 @@ pub enum ParameterMode {
 
 pub type RegisterSize = i64;
 
-pub struct IntCodeInterpreter {
-    memory: Vec<RegisterSize>,
-    inputs: Vec<RegisterSize>,
-    output: String,
-    show_output: bool,
-    pipe_mode: bool,
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum IntcodeError<T = RegisterSize> {
+    UnknownOpcode {
+        instruction_pointer: usize,
+        opcode: T,
+    },
+    UnknownParameterMode {
+        instruction_pointer: usize,
+        opcode: T,
+        mode: T,
+    },
+    ImmediateModeWrite {
+        instruction_pointer: usize,
+        opcode: T,
+    },
+    NegativeAddress {
+        instruction_pointer: usize,
+        opcode: T,
+        address: T,
+    },
+    // Only possible with registers wider than an address
+    AddressOutOfRange {
+        instruction_pointer: usize,
+        opcode: T,
+        address: T,
+    },
+    // Addition or multiplication, including relative addressing, under the checked policy
+    Overflow {
+        instruction_pointer: usize,
+        opcode: T,
+        operands: (T, T),
+    },
+    InputFailed {
+        instruction_pointer: usize,
+        message: String,
+    },
+    OutputFailed {
+        instruction_pointer: usize,
+        message: String,
+    },
+    TraceFailed {
+        instruction_pointer: usize,
+        message: String,
+    },
+}
+
+impl<T: fmt::Display> fmt::Display for IntcodeError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntcodeError::UnknownOpcode {
+                instruction_pointer,
+                opcode,
+            } => write!(f, "unknown opcode {} at {}", opcode, instruction_pointer),
+            IntcodeError::UnknownParameterMode {
+                instruction_pointer,
+                opcode,
+                mode,
+            } => write!(
+                f,
+                "unknown parameter mode {} in opcode {} at {}",
+                mode, opcode, instruction_pointer
+            ),
+            IntcodeError::ImmediateModeWrite {
+                instruction_pointer,
+                opcode,
+            } => write!(
+                f,
+                "immediate mode write in opcode {} at {}",
+                opcode, instruction_pointer
+            ),
+            IntcodeError::NegativeAddress {
+                instruction_pointer,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "negative address {} used by opcode {} at {}",
+                address, opcode, instruction_pointer
+            ),
+            IntcodeError::AddressOutOfRange {
+                instruction_pointer,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "address {} used by opcode {} at {} is out of range",
+                address, opcode, instruction_pointer
+            ),
+            IntcodeError::Overflow {
+                instruction_pointer,
+                opcode,
+                operands: (left, right),
+            } => write!(
+                f,
+                "overflow on {} and {} in opcode {} at {}",
+                left, right, opcode, instruction_pointer
+            ),
+            IntcodeError::InputFailed {
+                instruction_pointer,
+                message,
+            } => write!(f, "input failed at {}: {}", instruction_pointer, message),
+            IntcodeError::OutputFailed {
+                instruction_pointer,
+                message,
+            } => write!(f, "output failed at {}: {}", instruction_pointer, message),
+            IntcodeError::TraceFailed {
+                instruction_pointer,
+                message,
+            } => write!(f, "tracing failed at {}: {}", instruction_pointer, message),
+        }
+    }
+}
+
+impl<T: fmt::Debug + fmt::Display> Error for IntcodeError<T> {}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RunState {
+    Halted,
+    AwaitingInput,
+    LimitExceeded(Limit),
+    LoopDetected(LoopInfo),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StepResult<T = RegisterSize> {
+    Continue,
+    NeedsInput,
+    Output(T),
+    Halted,
+    // Only from run_until, which stops short of the instruction that would break the limit
+    LimitExceeded(Limit),
+    // Only from run_until, with loop detection turned on
+    LoopDetected(LoopInfo),
+}
+
+// Checking the clock every instruction would cost more than the rest of the loop
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+// Addresses past this are decoded every time rather than growing the cache without bound
+const DECODE_CACHE_SIZE: usize = 1 << 16;
+
+#[derive(Debug, Copy, Clone)]
+struct DecodedInstruction {
+    opcode: RegisterSize,
+    modes: [ParameterMode; MAX_PARAMETERS],
+}
+
+// Registers are i64 unless another width is picked at construction, with something like
+// IntCodeInterpreter::<BigInt>::default()
+pub struct IntCodeInterpreter<T = RegisterSize> {
+    memory: Memory<T>,
+    decoded: Vec<Option<DecodedInstruction>>,
+    inputs: Vec<T>,
+    outputs: VecDeque<T>,
+    input_source: Option<Box<dyn InputSource<T> + Send>>,
+    output_sink: Option<Box<dyn OutputSink<T> + Send>>,
+    tracer: Option<Box<dyn Tracer<T> + Send>>,
+    limits: Limits,
+    arithmetic: ArithmeticPolicy,
+    instructions: Arc<InstructionSet<T>>,
+    loop_detector: Option<LoopDetector<T>>,
+    // Operand reads only have &self, hence the RefCell
+    profile: Option<RefCell<Profile>>,
+    coverage: Option<Coverage>,
     running: bool,
     instruction_pointer: usize,
-    relative_offset: RegisterSize,
-    memory_size: usize,
+    relative_offset: T,
 }
 
-impl IntCodeInterpreter {
-    pub fn new() -> Self {
+impl<T: Register> Default for IntCodeInterpreter<T> {
+    fn default() -> Self {
         IntCodeInterpreter {
-            memory: Vec::new(),
+            memory: Memory::default(),
+            decoded: Vec::new(),
             inputs: Vec::new(),
-            output: String::new(),
-            show_output: true,
-            pipe_mode: false,
+            outputs: VecDeque::new(),
+            input_source: None,
+            output_sink: None,
+            tracer: None,
+            limits: Limits::new(),
+            arithmetic: ArithmeticPolicy::default(),
+            instructions: Arc::new(InstructionSet::standard()),
+            loop_detector: None,
+            profile: None,
+            coverage: None,
             running: false,
             instruction_pointer: 0,
-            relative_offset: 0,
-            memory_size: 0,
+            relative_offset: T::default(),
         }
     }
+}
 
-    pub fn _get_parameter_value(
+impl IntCodeInterpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<T: Register> IntCodeInterpreter<T> {
+    fn _current_opcode(&self) -> T {
+        self.memory.read(self.instruction_pointer)
+    }
+
+    // The opcode as a plain number, or None if it's too big to be a valid one
+    fn _current_opcode_number(&self) -> Option<RegisterSize> {
+        self.memory[self.instruction_pointer].to_i64()
+    }
+
+    fn _to_address(&self, address: T) -> Result<usize, IntcodeError<T>> {
+        if address.is_negative() {
+            return Err(IntcodeError::NegativeAddress {
+                instruction_pointer: self.instruction_pointer,
+                opcode: self._current_opcode(),
+                address,
+            });
+        }
+
+        address
+            .to_usize()
+            .ok_or_else(|| IntcodeError::AddressOutOfRange {
+                instruction_pointer: self.instruction_pointer,
+                opcode: self._current_opcode(),
+                address,
+            })
+    }
+
+    fn _read_memory(&self, target_address: usize) -> T {
+        self.memory.read(target_address)
+    }
+
+    fn _overflow(&self, left: T, right: T) -> IntcodeError<T> {
+        IntcodeError::Overflow {
+            instruction_pointer: self.instruction_pointer,
+            opcode: self._current_opcode(),
+            operands: (left, right),
+        }
+    }
+
+    fn _add(&self, left: T, right: T) -> Result<T, IntcodeError<T>> {
+        match left.add_with(&right, self.arithmetic) {
+            Some(sum) => Ok(sum),
+            None => Err(self._overflow(left, right)),
+        }
+    }
+
+    fn _multiply(&self, left: T, right: T) -> Result<T, IntcodeError<T>> {
+        match left.mul_with(&right, self.arithmetic) {
+            Some(product) => Ok(product),
+            None => Err(self._overflow(left, right)),
+        }
+    }
+
+    // None for immediate mode, where the operand is the value itself
+    fn _parameter_address(
         &self,
         address_offset: usize,
         parameter_mode: ParameterMode,
-    ) -> RegisterSize {
-        let target_memory = self.memory[self.instruction_pointer + address_offset];
+    ) -> Result<Option<usize>, IntcodeError<T>> {
+        let target_memory = self._read_memory(self.instruction_pointer + address_offset);
 
         match parameter_mode {
-            ParameterMode::PositionMode => self.memory[target_memory as usize],
-            ParameterMode::ImmediateMode => self.memory[self.instruction_pointer + address_offset],
-            ParameterMode::RelativeMode => {
-                self.memory[(target_memory + self.relative_offset) as usize]
-            }
+            ParameterMode::PositionMode => Ok(Some(self._to_address(target_memory)?)),
+            ParameterMode::ImmediateMode => Ok(None),
+            ParameterMode::RelativeMode => Ok(Some(
+                self._to_address(self._add(target_memory, self.relative_offset.clone())?)?,
+            )),
         }
     }
 
-    fn _read_memory(&self, target_address: usize) -> RegisterSize {
-        self.memory[target_address]
+    pub fn _get_parameter_value(
+        &self,
+        address_offset: usize,
+        parameter_mode: ParameterMode,
+    ) -> Result<T, IntcodeError<T>> {
+        match self._parameter_address(address_offset, parameter_mode)? {
+            Some(address) => {
+                if let Some(profile) = &self.profile {
+                    profile.borrow_mut().record_read(address);
+                }
+
+                Ok(self._read_memory(address))
+            }
+            None => Ok(self._read_memory(self.instruction_pointer + address_offset)),
+        }
     }
 
     pub fn _set_memory_address(
         &mut self,
         address_offset: usize,
-        value: RegisterSize,
+        value: T,
         parameter_mode: ParameterMode,
-    ) -> () {
-        let target_address = self.memory[(self.instruction_pointer + address_offset) as usize];
+    ) -> Result<(), IntcodeError<T>> {
+        let target_address = self._read_memory(self.instruction_pointer + address_offset);
 
-        match parameter_mode {
-            ParameterMode::PositionMode => {
-                self.memory[target_address as usize] = value;
-            }
+        let target_address = match parameter_mode {
+            ParameterMode::PositionMode => self._to_address(target_address)?,
             ParameterMode::RelativeMode => {
-                self.memory[(target_address + self.relative_offset) as usize] = value
+                self._to_address(self._add(target_address, self.relative_offset.clone())?)?
+            }
+            ParameterMode::ImmediateMode => {
+                return Err(IntcodeError::ImmediateModeWrite {
+                    instruction_pointer: self.instruction_pointer,
+                    opcode: self._current_opcode(),
+                })
             }
-            ParameterMode::ImmediateMode => panic!("Unexpected write mode detected"),
+        };
+
+        if let Some(detector) = self.loop_detector.as_mut() {
+            detector.record_write(target_address, &self.memory[target_address], &value);
+        }
+
+        if let Some(profile) = self.profile.as_mut() {
+            profile.get_mut().record_write(target_address);
+        }
+
+        self.memory.write(target_address, value);
+
+        // Self-modifying code: whatever was decoded here before is stale now
+        if let Some(decoded) = self.decoded.get_mut(target_address) {
+            *decoded = None;
         }
+
+        Ok(())
+    }
+
+    fn _get_parameter_modes(
+        &self,
+        opcode: RegisterSize,
+    ) -> Result<[ParameterMode; MAX_PARAMETERS], IntcodeError<T>> {
+        let mut modes = [ParameterMode::PositionMode; MAX_PARAMETERS];
+        let mut digits = opcode / 100;
+        let mut index = 0;
+
+        // Modes past the third can't be used, but they still have to be valid
+        while digits != 0 {
+            let mode = match digits % 10 {
+                0 => Some(ParameterMode::PositionMode),
+                1 => Some(ParameterMode::ImmediateMode),
+                2 => Some(ParameterMode::RelativeMode),
+                _ => None,
+            };
+
+            // Modes the instruction set leaves out are as unknown as ones that never existed
+            let mode = match mode.filter(|mode| self.instructions.allows_mode(*mode)) {
+                Some(mode) => mode,
+                None => {
+                    return Err(IntcodeError::UnknownParameterMode {
+                        instruction_pointer: self.instruction_pointer,
+                        opcode: T::from_i64(opcode),
+                        mode: T::from_i64(digits % 10),
+                    })
+                }
+            };
+
+            if index < modes.len() {
+                modes[index] = mode;
+            }
+
+            digits /= 10;
+            index += 1;
+        }
+
+        Ok(modes)
+    }
+
+    fn _decode(&mut self) -> Result<DecodedInstruction, IntcodeError<T>> {
+        if let Some(Some(decoded)) = self.decoded.get(self.instruction_pointer) {
+            return Ok(*decoded);
+        }
+
+        let opcode = match self._current_opcode_number() {
+            Some(opcode) if opcode >= 0 => opcode,
+            _ => {
+                return Err(IntcodeError::UnknownOpcode {
+                    instruction_pointer: self.instruction_pointer,
+                    opcode: self._current_opcode(),
+                })
+            }
+        };
+
+        let decoded = DecodedInstruction {
+            opcode,
+            modes: self._get_parameter_modes(opcode)?,
+        };
+
+        if self.instruction_pointer < DECODE_CACHE_SIZE {
+            if self.decoded.len() <= self.instruction_pointer {
+                self.decoded.resize(self.instruction_pointer + 1, None);
+            }
+
+            self.decoded[self.instruction_pointer] = Some(decoded);
+        }
+
+        Ok(decoded)
+    }
+
+    // Consulted whenever the input queue runs dry
+    pub fn set_input_source<S: InputSource<T> + Send + 'static>(&mut self, source: S) {
+        self.input_source = Some(Box::new(source));
+    }
+
+    // Receives every output as well as the output queue
+    pub fn set_output_sink<S: OutputSink<T> + Send + 'static>(&mut self, sink: S) {
+        self.output_sink = Some(Box::new(sink));
+    }
+
+    pub fn clear_input_source(&mut self) {
+        self.input_source = None;
+    }
+
+    pub fn clear_output_sink(&mut self) {
+        self.output_sink = None;
+    }
+
+    // Records every instruction that completes, including the final halt
+    pub fn set_tracer<R: Tracer<T> + Send + 'static>(&mut self, tracer: R) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    pub fn clear_tracer(&mut self) {
+        self.tracer = None;
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    pub fn set_arithmetic(&mut self, policy: ArithmeticPolicy) {
+        self.arithmetic = policy;
+    }
+
+    pub fn arithmetic(&self) -> ArithmeticPolicy {
+        self.arithmetic
+    }
+
+    // Replaces the opcodes the machine understands. Forks share the set
+    pub fn set_instruction_set(&mut self, instructions: InstructionSet<T>) {
+        self.instructions = Arc::new(instructions);
+        // Cached decodes were checked against the old set's modes
+        self.decoded.clear();
+    }
+
+    pub fn set_isa_level(&mut self, level: IsaLevel) {
+        self.set_instruction_set(InstructionSet::for_level(level));
+    }
+
+    pub fn instruction_set(&self) -> &InstructionSet<T> {
+        &self.instructions
+    }
+
+    // Off by default, as it slows every instruction down a little
+    pub fn set_loop_detection(&mut self, enabled: bool) {
+        self.loop_detector = if enabled {
+            Some(LoopDetector::new())
+        } else {
+            None
+        };
+    }
+
+    // Profiling starts afresh each time it's turned on
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profile = if enabled {
+            Some(RefCell::new(Profile::new()))
+        } else {
+            None
+        };
+    }
+
+    pub fn profile(&self) -> Option<Profile> {
+        self.profile
+            .as_ref()
+            .map(|profile| profile.borrow().clone())
     }
 
-    pub fn set_show_output(&mut self, show_output: bool) {
-        self.show_output = show_output;
+    // Hands over the counts so far and starts counting again from zero
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile
+            .as_mut()
+            .map(|profile| profile.replace(Profile::new()))
     }
 
-    pub fn set_memory_size(&mut self, memory_size: usize) {
-        self.memory_size = memory_size;
+    // Like profiling, coverage starts afresh each time it's turned on
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.coverage = if enabled { Some(Coverage::new()) } else { None };
     }
 
-    pub fn set_pipe_mode(&mut self, pipe_mode: bool) {
-        self.pipe_mode = pipe_mode;
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
     }
 
-    pub fn set_inputs(&mut self, inputs: &Vec<RegisterSize>) {
-        self.inputs = inputs.clone();
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.as_mut().map(std::mem::take)
     }
 
-    pub fn add_input(&mut self, input: RegisterSize) {
+    pub fn set_inputs(&mut self, inputs: &[T]) {
+        self.inputs = inputs.to_vec();
+    }
+
+    pub fn add_input(&mut self, input: T) {
         self.inputs.push(input);
     }
 
-    pub fn run(&mut self) -> () {
-        if self.memory.len() < self.memory_size {
-            // Ensure we have a suitable amount of memory as requested by the user
-            self.memory.extend(vec![0i64; self.memory_size]);
+    pub fn step(&mut self) -> Result<StepResult<T>, IntcodeError<T>> {
+        // Only needed once the instruction has run, by which time it may have overwritten itself
+        let executing = if self.profile.is_some() || self.coverage.is_some() {
+            self._current_opcode_number()
+                .map(|opcode| (self.instruction_pointer, opcode))
+        } else {
+            None
+        };
+
+        let result = if self.tracer.is_some() {
+            self._traced_step()?
+        } else {
+            self._execute()?
+        };
+
+        // Waiting for input doesn't count as executing the instruction, it'll be run again
+        if let StepResult::NeedsInput = result {
+            return Ok(result);
+        }
+
+        if let (Some(coverage), Some((address, _))) = (self.coverage.as_mut(), executing) {
+            coverage.record(address);
+        }
+
+        if let (Some(profile), Some((address, opcode))) = (self.profile.as_mut(), executing) {
+            let profile = profile.get_mut();
+
+            match result {
+                StepResult::Output(_) => profile.outputs += 1,
+                _ if opcode % 100 == 3 => profile.inputs += 1,
+                _ => {}
+            }
+
+            profile.record_instruction(address, opcode);
+        }
+
+        Ok(result)
+    }
+
+    fn _traced_step(&mut self) -> Result<StepResult<T>, IntcodeError<T>> {
+        let instruction_pointer = self.instruction_pointer;
+        let relative_offset = self.relative_offset.clone();
+
+        let (opcode, modes, operands, write_addresses) = match self._trace_operands() {
+            Some(decoded) => decoded,
+            // Not a valid instruction, so let the interpreter report why
+            None => return self._execute(),
+        };
+
+        let result = self._execute()?;
+
+        if let StepResult::NeedsInput = result {
+            return Ok(result);
         }
 
+        let writes = write_addresses
+            .into_iter()
+            .map(|address| (address, self._read_memory(address)))
+            .collect::<Vec<(usize, T)>>();
+
+        let event = TraceEvent {
+            instruction_pointer,
+            opcode,
+            modes,
+            operands,
+            relative_offset: Some(self.relative_offset.clone()).filter(|rb| *rb != relative_offset),
+            input: if opcode % 100 == 3 {
+                writes.first().map(|(_, value)| value.clone())
+            } else {
+                None
+            },
+            output: match &result {
+                StepResult::Output(value) => Some(value.clone()),
+                _ => None,
+            },
+            writes,
+        };
+
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer
+                .record(&event)
+                .map_err(|error| IntcodeError::TraceFailed {
+                    instruction_pointer,
+                    message: error.to_string(),
+                })?;
+        }
+
+        Ok(result)
+    }
+
+    // Resolves operands the way the instruction will see them: values for reads, addresses for
+    // writes. Reads that can't be resolved, like a jump target that's never taken, are left raw
+    #[allow(clippy::type_complexity)]
+    fn _trace_operands(&self) -> Option<(RegisterSize, Vec<ParameterMode>, Vec<T>, Vec<usize>)> {
+        let opcode = self._current_opcode_number()?;
+        let operation = self.instructions.get(opcode)?;
+        let modes = self._get_parameter_modes(opcode).ok()?[..operation.arity].to_vec();
+
+        let mut operands = Vec::new();
+        let mut write_addresses = Vec::new();
+
+        for (index, mode) in modes.iter().enumerate() {
+            let raw = self._read_memory(self.instruction_pointer + index + 1);
+
+            if operation.writes.contains(&index) {
+                let address = match mode {
+                    ParameterMode::PositionMode => raw,
+                    ParameterMode::RelativeMode => raw.checked_add(&self.relative_offset)?,
+                    ParameterMode::ImmediateMode => return None,
+                };
+
+                write_addresses.push(address.to_usize()?);
+                operands.push(address);
+            } else {
+                let value = match self._parameter_address(index + 1, *mode) {
+                    Ok(Some(address)) => self._read_memory(address),
+                    _ => raw,
+                };
+
+                operands.push(value);
+            }
+        }
+
+        Some((opcode, modes, operands, write_addresses))
+    }
+
+    fn _execute(&mut self) -> Result<StepResult<T>, IntcodeError<T>> {
         self.running = true;
 
-        loop {
-            let opcode = self.memory[self.instruction_pointer as usize];
-
-            // Kind of gross, but prepend with a bunch of zeroes in case we need a default
-            let mut opcode_string = "00000000000".to_owned() + opcode.to_string().as_str();
-            opcode_string.pop();
-            opcode_string.pop();
-
-            let parameter_modes = opcode_string
-                .chars()
-                .rev()
-                .map(|n| match n {
-                    '0' => ParameterMode::PositionMode,
-                    '1' => ParameterMode::ImmediateMode,
-                    '2' => ParameterMode::RelativeMode,
-                    _ => panic!("Unknown parameter mode"),
+        let DecodedInstruction { opcode, modes } = self._decode()?;
+
+        // Shared so the operation can borrow the interpreter mutably while it runs
+        let instructions = self.instructions.clone();
+        let operation = match instructions.get(opcode) {
+            Some(operation) => operation,
+            None => {
+                return Err(IntcodeError::UnknownOpcode {
+                    instruction_pointer: self.instruction_pointer,
+                    opcode: T::from_i64(opcode),
                 })
-                .collect::<Vec<ParameterMode>>();
-
-            match opcode % 100 {
-                1 => {
-                    self._set_memory_address(
-                        3,
-                        self._get_parameter_value(1, parameter_modes[0])
-                            + self._get_parameter_value(2, parameter_modes[1]),
-                        parameter_modes[2],
-                    );
-
-                    self.instruction_pointer += 4;
-                }
-                2 => {
-                    self._set_memory_address(
-                        3,
-                        self._get_parameter_value(1, parameter_modes[0])
-                            * self._get_parameter_value(2, parameter_modes[1]),
-                        parameter_modes[2],
-                    );
-
-                    self.instruction_pointer += 4;
-                }
-                3 => {
-                    if self.inputs.is_empty() {
-                        if self.pipe_mode {
-                            break;
-                        }
+            }
+        };
 
-                        let value: String = read!();
+        let mut execution = Execution::new(self, modes);
+        let result = operation.execute(&mut execution)?;
+        let jumped = execution.jumped();
 
-                        let input = RegisterSize::from_str(&value).unwrap();
+        match result {
+            StepResult::Halted => self.running = false,
+            // Leave the instruction pointer alone so the instruction is retried
+            StepResult::NeedsInput => {}
+            _ if !jumped => self.instruction_pointer += operation.arity + 1,
+            _ => {}
+        }
 
-                        self._set_memory_address(1, input, parameter_modes[0]);
-                    } else {
-                        let buffer_input = self.inputs.remove(0);
-                        self._set_memory_address(1, buffer_input, parameter_modes[0])
-                    }
+        Ok(result)
+    }
 
-                    self.instruction_pointer += 2;
-                }
-                4 => {
-                    let current_output = self._get_parameter_value(1, parameter_modes[0]);
+    // Keeps stepping until the predicate accepts a result, or the machine can't make progress.
+    // Hitting a limit leaves the machine ready to carry on with another call
+    pub fn run_until<F>(&mut self, predicate: F) -> Result<StepResult<T>, IntcodeError<T>>
+    where
+        F: FnMut(&StepResult<T>) -> bool,
+    {
+        // Whatever happened between calls, like new input, isn't something the detector saw
+        if let Some(detector) = self.loop_detector.as_mut() {
+            detector.reset();
+        }
 
-                    if self.show_output {
-                        print!("{}", current_output);
-                    }
+        self.run_until_with(&mut Progress::new(), predicate)
+    }
 
-                    self.output += current_output.to_string().as_str();
+    // run_until, counting towards the limits from wherever the progress has got to
+    pub(crate) fn run_until_with<F>(
+        &mut self,
+        progress: &mut Progress,
+        mut predicate: F,
+    ) -> Result<StepResult<T>, IntcodeError<T>>
+    where
+        F: FnMut(&StepResult<T>) -> bool,
+    {
+        loop {
+            if let Some(limit) = self._check_limits(progress) {
+                return Ok(StepResult::LimitExceeded(limit));
+            }
 
-                    self.instruction_pointer += 2;
-                }
-                5 => {
-                    if self._get_parameter_value(1, parameter_modes[0]) != 0 {
-                        self.instruction_pointer =
-                            self._get_parameter_value(2, parameter_modes[1]) as usize;
-                    } else {
-                        self.instruction_pointer += 3;
-                    }
-                }
-                6 => {
-                    if self._get_parameter_value(1, parameter_modes[0]) == 0 {
-                        self.instruction_pointer =
-                            self._get_parameter_value(2, parameter_modes[1]) as usize;
-                    } else {
-                        self.instruction_pointer += 3;
-                    }
-                }
-                7 => {
-                    if self._get_parameter_value(1, parameter_modes[0])
-                        < self._get_parameter_value(2, parameter_modes[1])
-                    {
-                        self._set_memory_address(3, 1, parameter_modes[2]);
-                    } else {
-                        self._set_memory_address(3, 0, parameter_modes[2]);
-                    }
-
-                    self.instruction_pointer += 4;
-                }
-                8 => {
-                    if self._get_parameter_value(1, parameter_modes[0])
-                        == self._get_parameter_value(2, parameter_modes[1])
-                    {
-                        self._set_memory_address(3, 1, parameter_modes[2]);
-                    } else {
-                        self._set_memory_address(3, 0, parameter_modes[2]);
-                    }
-
-                    self.instruction_pointer += 4;
-                }
-                9 => {
-                    let offset = self._get_parameter_value(1, parameter_modes[0]);
-                    self.relative_offset += offset;
+            let executed = match self.loop_detector {
+                Some(_) => self
+                    ._current_opcode_number()
+                    .map(|opcode| (self.instruction_pointer, opcode)),
+                None => None,
+            };
+
+            let result = self.step()?;
+            progress.instructions += 1;
+
+            if let StepResult::Output(_) = result {
+                progress.outputs += 1;
+            }
+
+            if predicate(&result) || matches!(result, StepResult::NeedsInput | StepResult::Halted) {
+                return Ok(result);
+            }
 
-                    self.instruction_pointer += 2;
+            // Memory can only be checked after the write, but the instruction has completed so
+            // it's still safe to resume from here
+            if let Some(max_memory) = self.limits.max_memory {
+                if self.memory.high_water_mark() > max_memory {
+                    return Ok(StepResult::LimitExceeded(Limit::Memory(max_memory)));
                 }
-                99 => {
-                    self.running = false;
-                    break;
+            }
+
+            if let (Some(detector), Some((address, opcode))) =
+                (self.loop_detector.as_mut(), executed)
+            {
+                if !matches!(result, StepResult::Continue) || opcode % 100 == 3 {
+                    detector.reset();
+                } else if let Some(info) = detector.check(
+                    (
+                        address,
+                        self.instructions
+                            .get(opcode)
+                            .map_or(1, |operation| operation.arity + 1),
+                    ),
+                    self.instruction_pointer,
+                    &self.relative_offset,
+                    &self.inputs,
+                    &self.memory,
+                ) {
+                    return Ok(StepResult::LoopDetected(info));
                 }
-                x => panic!(format!("Unable to execute program, found {}", x).to_string()),
             }
         }
     }
 
-    pub fn output(&self) -> &String {
-        &self.output
+    fn _check_limits(&self, progress: &Progress) -> Option<Limit> {
+        if let Some(max_instructions) = self.limits.max_instructions {
+            if progress.instructions >= max_instructions {
+                return Some(Limit::Instructions(max_instructions));
+            }
+        }
+
+        if let Some(max_duration) = self.limits.max_duration {
+            if progress.instructions.is_multiple_of(TIME_CHECK_INTERVAL)
+                && progress.started.elapsed() >= max_duration
+            {
+                return Some(Limit::Duration(max_duration));
+            }
+        }
+
+        if let Some(max_outputs) = self.limits.max_outputs {
+            if progress.outputs >= max_outputs
+                && self._current_opcode_number().map(|op| op % 100) == Some(4)
+            {
+                return Some(Limit::Outputs(max_outputs));
+            }
+        }
+
+        None
+    }
+
+    fn _read_source_input(&mut self) -> Result<Option<T>, IntcodeError<T>> {
+        match self.input_source.as_mut() {
+            Some(source) => source
+                .read_input()
+                .map_err(|error| IntcodeError::InputFailed {
+                    instruction_pointer: self.instruction_pointer,
+                    message: error.to_string(),
+                }),
+            None => Ok(None),
+        }
+    }
+
+    pub fn run(&mut self) -> Result<RunState, IntcodeError<T>> {
+        let result = self.run_until(|_| false);
+        self.flush_tracer()?;
+
+        match result? {
+            StepResult::NeedsInput => Ok(RunState::AwaitingInput),
+            StepResult::LimitExceeded(limit) => Ok(RunState::LimitExceeded(limit)),
+            StepResult::LoopDetected(info) => Ok(RunState::LoopDetected(info)),
+            _ => Ok(RunState::Halted),
+        }
+    }
+
+    // run() does this itself, but callers driving step() need to when they're done
+    pub fn flush_tracer(&mut self) -> Result<(), IntcodeError<T>> {
+        match self.tracer.as_mut() {
+            Some(tracer) => tracer.flush().map_err(|error| IntcodeError::TraceFailed {
+                instruction_pointer: self.instruction_pointer,
+                message: error.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn outputs(&self) -> &VecDeque<T> {
+        &self.outputs
+    }
+
+    pub fn take_output(&mut self) -> Option<T> {
+        self.outputs.pop_front()
+    }
+
+    pub fn drain_outputs(&mut self) -> Vec<T> {
+        self.outputs.drain(..).collect()
+    }
+
+    pub fn last_output(&self) -> Option<T> {
+        self.outputs.back().cloned()
+    }
+
+    // Comma separated, so multi-digit and negative values stay unambiguous
+    pub fn output_string(&self) -> String {
+        self.outputs
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<String>>()
+            .join(",")
     }
 
     pub fn clear_output(&mut self) {
-        self.output.clear();
+        self.outputs.clear();
     }
 
     pub fn halted(&self) -> bool {
         !self.running
     }
 
-    pub fn memory(&self) -> &Vec<RegisterSize> {
+    pub fn memory(&self) -> &Memory<T> {
         &self.memory
     }
 
-    pub fn reset(&mut self, program: &Vec<RegisterSize>) -> () {
+    pub fn instruction_pointer(&self) -> usize {
+        self.instruction_pointer
+    }
+
+    pub fn relative_offset(&self) -> T {
+        self.relative_offset.clone()
+    }
+
+    pub fn pending_inputs(&self) -> &[T] {
+        &self.inputs
+    }
+
+    // The child shares memory pages with the parent until one of them writes to a page. I/O
+    // sources, sinks and tracers can't be shared, so the child starts without any. Copying the
+    // decode cache would cost as much as the fork saves, so the child fills its own as it goes
+    pub fn fork(&self) -> Self {
+        IntCodeInterpreter {
+            memory: self.memory.clone(),
+            decoded: Vec::new(),
+            inputs: self.inputs.clone(),
+            outputs: self.outputs.clone(),
+            input_source: None,
+            output_sink: None,
+            tracer: None,
+            limits: self.limits,
+            arithmetic: self.arithmetic,
+            instructions: self.instructions.clone(),
+            loop_detector: self.loop_detector.as_ref().map(|_| LoopDetector::new()),
+            profile: None,
+            coverage: None,
+            running: self.running,
+            instruction_pointer: self.instruction_pointer,
+            relative_offset: self.relative_offset.clone(),
+        }
+    }
+
+    // Captures everything needed to carry on later, but not the I/O or tracer configuration
+    pub fn snapshot(&self) -> Snapshot<T> {
+        Snapshot {
+            arithmetic: self.arithmetic,
+            isa_level: self.instructions.level(),
+            memory: self.memory.clone(),
+            instruction_pointer: self.instruction_pointer,
+            relative_offset: self.relative_offset.clone(),
+            inputs: self.inputs.clone(),
+            outputs: self.outputs.clone(),
+            running: self.running,
+        }
+    }
+
+    // A custom instruction set can't be saved, so restoring one of those keeps the current set
+    pub fn restore(&mut self, snapshot: &Snapshot<T>) {
+        if let Some(level) = snapshot.isa_level {
+            if self.instructions.level() != Some(level) {
+                self.set_isa_level(level);
+            }
+        }
+
+        self.arithmetic = snapshot.arithmetic;
+        self.memory = snapshot.memory.clone();
+        self.decoded.clear();
+        self.instruction_pointer = snapshot.instruction_pointer;
+        self.relative_offset = snapshot.relative_offset.clone();
+        self.inputs = snapshot.inputs.clone();
+        self.outputs = snapshot.outputs.clone();
+        self.running = snapshot.running;
+    }
+
+    pub fn reset(&mut self, program: &[T]) {
         self.instruction_pointer = 0;
-        self.relative_offset = 0;
-        self.memory = program.to_vec();
-        self.output = String::new();
+        self.relative_offset = T::default();
+        self.memory = Memory::from_program(program);
+        self.decoded.clear();
+        self.outputs = VecDeque::new();
         self.inputs = Vec::new();
     }
 }
@@ -258,87 +977,464 @@ mod test {
     #[test]
     fn test_simple_non_parameterised_run() {
         let mut interpreter = IntCodeInterpreter::new();
-        interpreter.reset(&&vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
-        interpreter.run();
+        interpreter.reset(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        interpreter.run().unwrap();
         assert_eq!(interpreter.memory()[0], 3500);
     }
 
     #[test]
     fn test_simple_parameterised_run() {
         let mut interpreter = IntCodeInterpreter::new();
-        interpreter.reset(&vec![1002, 4, 3, 4, 33]);
-        interpreter.run();
+        interpreter.reset(&[1002, 4, 3, 4, 33]);
+        interpreter.run().unwrap();
         assert_eq!(interpreter.memory()[4], 99);
     }
 
     #[test]
     fn test_negative_parameter() {
         let mut interpreter = IntCodeInterpreter::new();
-        interpreter.reset(&vec![1101, 100, -1, 4, 0]);
-        interpreter.run();
+        interpreter.reset(&[1101, 100, -1, 4, 0]);
+        interpreter.run().unwrap();
         assert_eq!(interpreter.memory()[4], 99);
     }
 
     #[test]
     fn test_basic_addition() {
         let mut interpreter = IntCodeInterpreter::new();
-        interpreter.reset(&vec![1, 0, 0, 0, 99]);
-        interpreter.run();
-        assert_eq!(interpreter.memory(), &[2, 0, 0, 0, 99]);
+        interpreter.reset(&[1, 0, 0, 0, 99]);
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.memory().to_vec(), vec![2, 0, 0, 0, 99]);
 
-        interpreter.reset(&vec![1, 1, 1, 4, 99, 5, 6, 0, 99]);
-        interpreter.run();
-        assert_eq!(interpreter.memory(), &[30, 1, 1, 4, 2, 5, 6, 0, 99]);
+        interpreter.reset(&[1, 1, 1, 4, 99, 5, 6, 0, 99]);
+        interpreter.run().unwrap();
+        assert_eq!(
+            interpreter.memory().to_vec(),
+            vec![30, 1, 1, 4, 2, 5, 6, 0, 99]
+        );
     }
 
     #[test]
     fn test_basic_multiplication() {
         let mut interpreter = IntCodeInterpreter::new();
-        interpreter.reset(&vec![2, 3, 0, 3, 99]);
-        interpreter.run();
-        assert_eq!(interpreter.memory(), &[2, 3, 0, 6, 99]);
+        interpreter.reset(&[2, 3, 0, 3, 99]);
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.memory().to_vec(), vec![2, 3, 0, 6, 99]);
 
-        interpreter.reset(&vec![2, 4, 4, 5, 99, 0]);
-        interpreter.run();
-        assert_eq!(interpreter.memory(), &[2, 4, 4, 5, 99, 9801]);
+        interpreter.reset(&[2, 4, 4, 5, 99, 0]);
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.memory().to_vec(), vec![2, 4, 4, 5, 99, 9801]);
     }
 
     #[test]
     fn test_basic_input_output() {
         let mut interpreter = IntCodeInterpreter::new();
-        interpreter.set_pipe_mode(true);
-        interpreter.reset(&vec![3, 0, 4, 0, 99]);
+        interpreter.reset(&[3, 0, 4, 0, 99]);
         interpreter.add_input(14);
-        interpreter.run();
-        assert_eq!(interpreter.memory(), &[14, 0, 4, 0, 99]);
-        assert_eq!(interpreter.output, "14");
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.memory().to_vec(), vec![14, 0, 4, 0, 99]);
+        assert_eq!(interpreter.drain_outputs(), vec![14]);
     }
 
     #[test]
     fn test_relative_mode() {
         let mut interpreter = IntCodeInterpreter::new();
-        interpreter.set_memory_size(100000);
         interpreter.reset(&vec![
             109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
         ]);
-        interpreter.run();
+        interpreter.run().unwrap();
         assert_eq!(
-            interpreter.output(),
-            "1091204-1100110011001008100161011006101099"
+            interpreter.drain_outputs(),
+            vec![109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99]
         );
     }
 
     #[test]
     fn test_large_number_support() {
         let mut interpreter = IntCodeInterpreter::new();
-        interpreter.set_memory_size(100000);
         let test_number: RegisterSize = 34915192;
-        interpreter.reset(&vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0]);
-        interpreter.run();
+        interpreter.reset(&[1102, 34915192, 34915192, 7, 4, 7, 99, 0]);
+        interpreter.run().unwrap();
 
         assert_eq!(
-            i64::from_str(interpreter.output()).unwrap(),
+            interpreter.last_output().unwrap(),
             test_number * test_number,
         );
     }
+
+    #[test]
+    fn test_arithmetic_policies() {
+        let mut interpreter = IntCodeInterpreter::new();
+        assert_eq!(interpreter.arithmetic(), ArithmeticPolicy::Checked);
+
+        let sum = [1101, RegisterSize::MAX, 1, 9, 1002, 9, 2, 9, 99, 0];
+
+        interpreter.reset(&sum);
+        assert_eq!(
+            interpreter.run(),
+            Err(IntcodeError::Overflow {
+                instruction_pointer: 0,
+                opcode: 1101,
+                operands: (RegisterSize::MAX, 1),
+            })
+        );
+
+        interpreter.set_arithmetic(ArithmeticPolicy::Wrapping);
+        interpreter.reset(&sum);
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.memory()[9], 0);
+
+        interpreter.set_arithmetic(ArithmeticPolicy::Saturating);
+        interpreter.reset(&sum);
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.memory()[9], RegisterSize::MAX);
+
+        // Relative addressing is arithmetic too
+        interpreter.set_arithmetic(ArithmeticPolicy::Checked);
+        interpreter.reset(&[109, RegisterSize::MAX, 204, 1, 99]);
+        assert_eq!(
+            interpreter.run().unwrap_err().to_string(),
+            format!("overflow on 1 and {} in opcode 204 at 2", RegisterSize::MAX)
+        );
+    }
+
+    #[test]
+    fn test_wide_registers() {
+        use num::BigInt;
+
+        // Squares its input twice, which overflows i64 for anything past 2^16
+        let program = [3, 13, 2, 13, 13, 13, 2, 13, 13, 13, 4, 13, 99];
+
+        let mut wide = IntCodeInterpreter::<i128>::default();
+        wide.reset(&program.map(i128::from));
+        wide.add_input(1 << 20);
+        wide.run().unwrap();
+        assert_eq!(wide.last_output(), Some(1 << 80));
+
+        let mut big = IntCodeInterpreter::<BigInt>::default();
+        big.reset(&program.map(BigInt::from));
+        big.add_input(BigInt::from(1) << 40);
+        big.run().unwrap();
+        assert_eq!(big.last_output(), Some(BigInt::from(1) << 160));
+
+        // Relative mode too, with a base far beyond what i64 could hold
+        let mut big = IntCodeInterpreter::<BigInt>::default();
+        big.reset(&[109, 1, 204, -1, 99].map(BigInt::from));
+        big.run().unwrap();
+        assert_eq!(big.drain_outputs(), vec![BigInt::from(109)]);
+
+        let huge = BigInt::from(1) << 100;
+        big.reset(&[BigInt::from(4), huge.clone(), BigInt::from(99)]);
+        assert_eq!(
+            big.run(),
+            Err(IntcodeError::AddressOutOfRange {
+                instruction_pointer: 0,
+                opcode: BigInt::from(4),
+                address: huge,
+            })
+        );
+    }
+
+    #[test]
+    fn test_run_reports_halt() {
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(&[3, 0, 99]);
+        assert_eq!(interpreter.run(), Ok(RunState::AwaitingInput));
+
+        interpreter.add_input(5);
+        assert_eq!(interpreter.run(), Ok(RunState::Halted));
+    }
+
+    #[test]
+    fn test_unknown_opcode_error() {
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(&[1101, 1, 1, 5, 42, 0]);
+        assert_eq!(
+            interpreter.run(),
+            Err(IntcodeError::UnknownOpcode {
+                instruction_pointer: 4,
+                opcode: 42,
+            })
+        );
+    }
+
+    #[test]
+    fn test_unknown_parameter_mode_error() {
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(&[301, 0, 0, 0, 99]);
+        assert_eq!(
+            interpreter.run(),
+            Err(IntcodeError::UnknownParameterMode {
+                instruction_pointer: 0,
+                opcode: 301,
+                mode: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn test_immediate_mode_write_error() {
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(&[11101, 1, 1, 0, 99]);
+        assert_eq!(
+            interpreter.run(),
+            Err(IntcodeError::ImmediateModeWrite {
+                instruction_pointer: 0,
+                opcode: 11101,
+            })
+        );
+    }
+
+    #[test]
+    fn test_negative_address_error() {
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(&[1, -1, 0, 0, 99]);
+        assert_eq!(
+            interpreter.run(),
+            Err(IntcodeError::NegativeAddress {
+                instruction_pointer: 0,
+                opcode: 1,
+                address: -1,
+            })
+        );
+    }
+
+    #[test]
+    fn test_memory_grows_on_write() {
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(&[1101, 7, 8, 5000, 4, 5000, 99]);
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.memory()[5000], 15);
+        assert_eq!(interpreter.memory()[6000], 0);
+        assert_eq!(interpreter.memory().high_water_mark(), 5001);
+        assert_eq!(interpreter.last_output(), Some(15));
+    }
+
+    #[test]
+    fn test_output_accessors() {
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(&[104, 12, 104, -3, 104, 450, 99]);
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.output_string(), "12,-3,450");
+        assert_eq!(interpreter.last_output(), Some(450));
+        assert_eq!(interpreter.take_output(), Some(12));
+        assert_eq!(interpreter.drain_outputs(), vec![-3, 450]);
+        assert_eq!(interpreter.take_output(), None);
+        assert_eq!(interpreter.last_output(), None);
+    }
+
+    #[test]
+    fn test_step() {
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(&[3, 9, 1001, 9, 1, 9, 4, 9, 99]);
+
+        assert_eq!(interpreter.step(), Ok(StepResult::NeedsInput));
+        assert_eq!(interpreter.step(), Ok(StepResult::NeedsInput));
+
+        interpreter.add_input(41);
+        assert_eq!(interpreter.step(), Ok(StepResult::Continue));
+        assert_eq!(interpreter.step(), Ok(StepResult::Continue));
+        assert_eq!(interpreter.step(), Ok(StepResult::Output(42)));
+        assert!(!interpreter.halted());
+        assert_eq!(interpreter.step(), Ok(StepResult::Halted));
+        assert!(interpreter.halted());
+    }
+
+    #[test]
+    fn test_run_until() {
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(&[104, 1, 104, 2, 3, 0, 104, 3, 99]);
+
+        let is_output = |result: &StepResult| matches!(result, StepResult::Output(_));
+
+        assert_eq!(interpreter.run_until(is_output), Ok(StepResult::Output(1)));
+        assert_eq!(interpreter.run_until(is_output), Ok(StepResult::Output(2)));
+        assert_eq!(interpreter.run_until(is_output), Ok(StepResult::NeedsInput));
+
+        interpreter.add_input(0);
+        assert_eq!(interpreter.run_until(is_output), Ok(StepResult::Output(3)));
+        assert_eq!(interpreter.run_until(is_output), Ok(StepResult::Halted));
+    }
+
+    #[test]
+    fn test_input_source_and_output_sink() {
+        let sink = QueueOutput::new();
+
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.set_input_source(QueueInput::new(&[3]));
+        interpreter.set_output_sink(sink.clone());
+        interpreter.reset(&[3, 11, 3, 12, 2, 11, 12, 13, 4, 13, 99, 0, 0, 0]);
+
+        // The queue is used first, then the source fills in
+        interpreter.add_input(7);
+        assert_eq!(interpreter.run(), Ok(RunState::Halted));
+        assert_eq!(sink.drain(), vec![21]);
+        assert_eq!(interpreter.drain_outputs(), vec![21]);
+    }
+
+    #[test]
+    fn test_closure_input_source() {
+        let mut next = 0;
+
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.set_input_source(FnInput::new(move || {
+            next += 1;
+
+            if next <= 2 {
+                Some(next * 10)
+            } else {
+                None
+            }
+        }));
+        interpreter.reset(&[3, 8, 4, 8, 1105, 1, 0, 0, 0]);
+
+        assert_eq!(interpreter.run(), Ok(RunState::AwaitingInput));
+        assert_eq!(interpreter.drain_outputs(), vec![10, 20]);
+    }
+
+    #[test]
+    fn test_fork() {
+        // Echoes inputs back doubled, with a big block of memory out past the program
+        let mut program = vec![3, 20000, 1002, 20000, 2, 20000, 4, 20000, 1105, 1, 0];
+        program.resize(30000, 1);
+
+        let mut parent = IntCodeInterpreter::new();
+        parent.reset(&program);
+        parent.add_input(5);
+        parent.run().unwrap();
+
+        let mut child = parent.fork();
+        assert_eq!(
+            child.memory().shared_pages(),
+            child.memory().allocated_pages()
+        );
+
+        child.add_input(7);
+        child.run().unwrap();
+        parent.add_input(9);
+        parent.run().unwrap();
+
+        assert_eq!(parent.drain_outputs(), vec![10, 18]);
+        assert_eq!(child.drain_outputs(), vec![10, 14]);
+        assert_eq!(parent.memory()[20000], 18);
+        assert_eq!(child.memory()[20000], 14);
+        assert_eq!(
+            child.memory().shared_pages(),
+            child.memory().allocated_pages() - 1
+        );
+    }
+
+    #[test]
+    fn test_self_modifying_code() {
+        // Runs an add, then rewrites it into a multiply and jumps back to run it again
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(&[
+            1101, 2, 3, 21, 4, 21, 1008, 0, 1102, 22, 1005, 22, 20, 1101, 1, 1101, 0, 1105, 1, 0,
+            99, 0, 0,
+        ]);
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.drain_outputs(), vec![5, 6]);
+    }
+
+    #[test]
+    fn test_instruction_and_time_limits() {
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(&[1105, 1, 0]);
+
+        interpreter.set_limits(Limits::new().instructions(1000));
+        assert_eq!(
+            interpreter.run(),
+            Ok(RunState::LimitExceeded(Limit::Instructions(1000)))
+        );
+
+        let timeout = std::time::Duration::from_millis(20);
+        interpreter.set_limits(Limits::new().duration(timeout));
+        assert_eq!(
+            interpreter.run(),
+            Ok(RunState::LimitExceeded(Limit::Duration(timeout)))
+        );
+    }
+
+    #[test]
+    fn test_output_limit_is_resumable() {
+        // Counts up to three, then halts
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(&[
+            101, 1, 14, 14, 4, 14, 1007, 14, 3, 15, 1005, 15, 0, 99, 0, 0,
+        ]);
+        interpreter.set_limits(Limits::new().outputs(2));
+
+        assert_eq!(
+            interpreter.run(),
+            Ok(RunState::LimitExceeded(Limit::Outputs(2)))
+        );
+        assert_eq!(interpreter.drain_outputs(), vec![1, 2]);
+        assert_eq!(interpreter.run(), Ok(RunState::Halted));
+        assert_eq!(interpreter.drain_outputs(), vec![3]);
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        // Writes ever further out, moving the relative base along each time
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(&[109, 1000, 21101, 1, 1, 0, 1105, 1, 0]);
+        interpreter.set_limits(Limits::new().memory(5000));
+
+        assert_eq!(
+            interpreter.run(),
+            Ok(RunState::LimitExceeded(Limit::Memory(5000)))
+        );
+        assert_eq!(interpreter.memory().high_water_mark(), 5001);
+        assert_eq!(interpreter.instruction_pointer(), 6);
+    }
+
+    #[test]
+    fn test_loop_detection() {
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.set_loop_detection(true);
+
+        interpreter.reset(&[1105, 1, 0]);
+        assert_eq!(
+            interpreter.run(),
+            Ok(RunState::LoopDetected(LoopInfo {
+                start: 0,
+                end: 3,
+                period: 1,
+            }))
+        );
+
+        // Counts 0, 1, 2, 3 round and round, after a bit of setup
+        interpreter.reset(&[
+            1101, 0, 0, 24, 1001, 24, 1, 24, 1008, 24, 3, 25, 1006, 25, 4, 1101, 0, 0, 24, 1105, 1,
+            4, 99, 99, 0, 0,
+        ]);
+        assert_eq!(
+            interpreter.run(),
+            Ok(RunState::LoopDetected(LoopInfo {
+                start: 4,
+                end: 22,
+                period: 11,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_loop_detection_ignores_slow_programs_and_io() {
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.set_loop_detection(true);
+
+        // Counts down from 100,000 before halting
+        interpreter.reset(&[1001, 8, -1, 8, 1005, 8, 0, 99, 100000]);
+        assert_eq!(interpreter.run(), Ok(RunState::Halted));
+
+        // Outputs the same thing forever, which might be what was wanted
+        interpreter.reset(&[104, 1, 1105, 1, 0]);
+        interpreter.set_limits(Limits::new().instructions(10000));
+        assert_eq!(
+            interpreter.run(),
+            Ok(RunState::LimitExceeded(Limit::Instructions(10000)))
+        );
+    }
 }
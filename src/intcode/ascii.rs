@@ -0,0 +1,198 @@
+use super::{IntCodeInterpreter, IntcodeError, RegisterSize, RunState};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+
+const NEWLINE: RegisterSize = 10;
+
+// Anything past this can't be a character, and is usually the answer the program worked out
+const MAX_ASCII: RegisterSize = 127;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ConsoleOutput {
+    // Without the newline
+    Line(String),
+    Value(RegisterSize),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ConsoleError {
+    NonAsciiInput(char),
+    Intcode(IntcodeError),
+}
+
+impl fmt::Display for ConsoleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConsoleError::NonAsciiInput(c) => write!(f, "can't send {:?}, it isn't ASCII", c),
+            ConsoleError::Intcode(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for ConsoleError {}
+
+// Talks to a program in text. Input goes in a line at a time, and output comes back as lines,
+// with any value too big to be a character kept apart from the text
+pub struct AsciiConsole {
+    interpreter: IntCodeInterpreter,
+    partial_line: String,
+    outputs: VecDeque<ConsoleOutput>,
+}
+
+impl AsciiConsole {
+    pub fn new(program: &[RegisterSize]) -> Self {
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(program);
+
+        AsciiConsole::from_interpreter(interpreter)
+    }
+
+    pub fn from_interpreter(interpreter: IntCodeInterpreter) -> Self {
+        AsciiConsole {
+            interpreter,
+            partial_line: String::new(),
+            outputs: VecDeque::new(),
+        }
+    }
+
+    pub fn interpreter(&self) -> &IntCodeInterpreter {
+        &self.interpreter
+    }
+
+    pub fn interpreter_mut(&mut self) -> &mut IntCodeInterpreter {
+        &mut self.interpreter
+    }
+
+    pub fn into_interpreter(self) -> IntCodeInterpreter {
+        self.interpreter
+    }
+
+    // Queues the text followed by a newline. Nothing is sent if any of it isn't ASCII
+    pub fn send_line(&mut self, text: &str) -> Result<(), ConsoleError> {
+        if let Some(c) = text.chars().find(|c| !c.is_ascii()) {
+            return Err(ConsoleError::NonAsciiInput(c));
+        }
+
+        for byte in text.bytes() {
+            self.interpreter.add_input(byte as RegisterSize);
+        }
+
+        self.interpreter.add_input(NEWLINE);
+
+        Ok(())
+    }
+
+    // Runs until the program halts or wants more input than it's been sent
+    pub fn run(&mut self) -> Result<RunState, ConsoleError> {
+        let result = self.interpreter.run();
+
+        // Whatever was printed before an error is often the best clue as to what went wrong
+        self.collect_output();
+
+        result.map_err(ConsoleError::Intcode)
+    }
+
+    // Moves everything the interpreter has output so far into lines and values. Only needed when
+    // driving the interpreter directly rather than through run()
+    pub fn collect_output(&mut self) {
+        for value in self.interpreter.drain_outputs() {
+            match value {
+                NEWLINE => {
+                    let line = std::mem::take(&mut self.partial_line);
+                    self.outputs.push_back(ConsoleOutput::Line(line));
+                }
+                0..=MAX_ASCII => self.partial_line.push(value as u8 as char),
+                _ => {
+                    // Keeps the order straight, rather than joining text from either side
+                    if !self.partial_line.is_empty() {
+                        let line = std::mem::take(&mut self.partial_line);
+                        self.outputs.push_back(ConsoleOutput::Line(line));
+                    }
+
+                    self.outputs.push_back(ConsoleOutput::Value(value));
+                }
+            }
+        }
+    }
+
+    pub fn take_output(&mut self) -> Option<ConsoleOutput> {
+        self.outputs.pop_front()
+    }
+
+    pub fn drain_outputs(&mut self) -> Vec<ConsoleOutput> {
+        self.outputs.drain(..).collect()
+    }
+
+    // Text with no newline yet, like a prompt waiting for an answer on the same line
+    pub fn partial_line(&self) -> &str {
+        &self.partial_line
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Prints a ? prompt, then echoes a line back followed by a large value
+    const ECHO: [RegisterSize; 17] = [
+        104, 63, 3, 100, 4, 100, 1008, 100, 10, 101, 1006, 101, 2, 104, 1000000, 99, 0,
+    ];
+
+    #[test]
+    fn test_lines_and_values() {
+        let mut console = AsciiConsole::new(&ECHO);
+
+        assert_eq!(console.run(), Ok(RunState::AwaitingInput));
+        assert_eq!(console.partial_line(), "?");
+        assert_eq!(console.take_output(), None);
+
+        console.send_line("Hi").unwrap();
+        assert_eq!(console.run(), Ok(RunState::Halted));
+        assert_eq!(
+            console.drain_outputs(),
+            vec![
+                ConsoleOutput::Line("?Hi".to_string()),
+                ConsoleOutput::Value(1000000),
+            ]
+        );
+        assert_eq!(console.partial_line(), "");
+    }
+
+    #[test]
+    fn test_partial_lines_end_at_values() {
+        let mut console = AsciiConsole::new(&[104, 65, 104, 128, 104, 66, 104, 10, 99]);
+        console.run().unwrap();
+
+        assert_eq!(
+            console.drain_outputs(),
+            vec![
+                ConsoleOutput::Line("A".to_string()),
+                ConsoleOutput::Value(128),
+                ConsoleOutput::Line("B".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut console = AsciiConsole::new(&ECHO);
+
+        assert_eq!(
+            console.send_line("café"),
+            Err(ConsoleError::NonAsciiInput('é'))
+        );
+        assert!(console.interpreter().pending_inputs().is_empty());
+
+        // The prompt still comes through when the program fails afterwards
+        let mut console = AsciiConsole::new(&[104, 62, 42]);
+        assert_eq!(
+            console.run(),
+            Err(ConsoleError::Intcode(IntcodeError::UnknownOpcode {
+                instruction_pointer: 2,
+                opcode: 42,
+            }))
+        );
+        assert_eq!(console.partial_line(), ">");
+    }
+}
@@ -0,0 +1,635 @@
+use super::{InstructionSet, ParameterMode, Register, RegisterSize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+// Far more than any real program, but small enough that the output can always be allocated
+const MAX_PROGRAM_SIZE: usize = 1 << 24;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl AsmError {
+    fn new(line: usize, message: String) -> Self {
+        AsmError { line, message }
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+// A label or number, plus or minus any further labels or numbers
+#[derive(Debug, Clone)]
+struct Expression {
+    terms: Vec<(bool, String)>,
+}
+
+impl Expression {
+    fn parse(text: &str, line: usize) -> Result<Self, AsmError> {
+        let mut terms = Vec::new();
+        let mut negative = false;
+        let mut current = String::new();
+
+        for c in text.chars().filter(|c| !c.is_whitespace()) {
+            if (c == '+' || c == '-') && !current.is_empty() {
+                terms.push((negative, current));
+                current = String::new();
+                negative = c == '-';
+            } else if c == '-' && current.is_empty() {
+                negative = !negative;
+            } else if c == '+' && current.is_empty() {
+                continue;
+            } else {
+                current.push(c);
+            }
+        }
+
+        if current.is_empty() {
+            return Err(AsmError::new(
+                line,
+                format!("expected a value in {:?}", text.trim()),
+            ));
+        }
+
+        terms.push((negative, current));
+
+        Ok(Expression { terms })
+    }
+
+    fn evaluate(
+        &self,
+        labels: &HashMap<String, usize>,
+        line: usize,
+    ) -> Result<RegisterSize, AsmError> {
+        let mut total: RegisterSize = 0;
+
+        for (negative, term) in &self.terms {
+            let value = if term.starts_with(|c: char| c.is_ascii_digit()) {
+                RegisterSize::from_str(term)
+                    .map_err(|_| AsmError::new(line, format!("invalid number {:?}", term)))?
+            } else {
+                match labels.get(term) {
+                    Some(address) => *address as RegisterSize,
+                    None => return Err(AsmError::new(line, format!("undefined label {:?}", term))),
+                }
+            };
+
+            let sum = if *negative {
+                total.checked_sub(value)
+            } else {
+                total.checked_add(value)
+            };
+
+            total = sum.ok_or_else(|| AsmError::new(line, "value out of range".to_string()))?;
+        }
+
+        Ok(total)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct AsmOperand {
+    mode: ParameterMode,
+    expression: Expression,
+}
+
+impl AsmOperand {
+    fn parse(text: &str, line: usize) -> Result<Self, AsmError> {
+        let text = text.trim();
+
+        if !text.starts_with('[') {
+            return Ok(AsmOperand {
+                mode: ParameterMode::ImmediateMode,
+                expression: Expression::parse(text, line)?,
+            });
+        }
+
+        let inner = match text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+            Some(inner) => inner.trim(),
+            None => {
+                return Err(AsmError::new(
+                    line,
+                    format!("unterminated operand {:?}", text),
+                ))
+            }
+        };
+
+        match inner.strip_prefix("rb") {
+            // Allow [rb] on its own as shorthand for [rb+0]
+            Some(offset) if offset.trim().is_empty() => Ok(AsmOperand {
+                mode: ParameterMode::RelativeMode,
+                expression: Expression::parse("0", line)?,
+            }),
+            Some(offset) if offset.trim_start().starts_with(['+', '-']) => Ok(AsmOperand {
+                mode: ParameterMode::RelativeMode,
+                expression: Expression::parse(offset, line)?,
+            }),
+            _ => Ok(AsmOperand {
+                mode: ParameterMode::PositionMode,
+                expression: Expression::parse(inner, line)?,
+            }),
+        }
+    }
+
+    fn stack_top() -> Self {
+        AsmOperand {
+            mode: ParameterMode::RelativeMode,
+            expression: Expression {
+                terms: vec![(false, "0".to_string())],
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Item {
+    Instruction {
+        opcode: RegisterSize,
+        operands: Vec<AsmOperand>,
+    },
+    Data(Vec<Expression>),
+    Zero(usize),
+}
+
+impl Item {
+    fn len(&self) -> usize {
+        match self {
+            Item::Instruction { operands, .. } => operands.len() + 1,
+            Item::Data(values) => values.len(),
+            Item::Zero(count) => *count,
+        }
+    }
+}
+
+fn split_operands(text: &str) -> Vec<&str> {
+    if text.trim().is_empty() {
+        Vec::new()
+    } else {
+        text.split(',').map(str::trim).collect()
+    }
+}
+
+fn instruction<T: Register>(
+    instructions: &InstructionSet<T>,
+    name: &str,
+    operands: Vec<AsmOperand>,
+    line: usize,
+) -> Result<Item, AsmError> {
+    let opcode = instructions
+        .find(name)
+        .ok_or_else(|| AsmError::new(line, format!("unknown instruction {:?}", name)))?
+        as RegisterSize;
+    let operation = instructions.get(opcode).unwrap();
+
+    if operands.len() != operation.arity {
+        return Err(AsmError::new(
+            line,
+            format!(
+                "{} expects {} operand(s), found {}",
+                name,
+                operation.arity,
+                operands.len()
+            ),
+        ));
+    }
+
+    for index in &operation.writes {
+        if operands[*index].mode == ParameterMode::ImmediateMode {
+            return Err(AsmError::new(
+                line,
+                format!("{} can't write to an immediate operand", name),
+            ));
+        }
+    }
+
+    Ok(Item::Instruction { opcode, operands })
+}
+
+// Relative base helpers treat rb as a stack pointer to the next free cell
+fn expand_macro<T: Register>(
+    instructions: &InstructionSet<T>,
+    name: &str,
+    operands: &[&str],
+    address: usize,
+    line: usize,
+) -> Result<Option<Vec<Item>>, AsmError> {
+    let expect = |count: usize| {
+        if operands.len() == count {
+            Ok(())
+        } else {
+            Err(AsmError::new(
+                line,
+                format!(
+                    "{} expects {} operand(s), found {}",
+                    name,
+                    count,
+                    operands.len()
+                ),
+            ))
+        }
+    };
+
+    let zero = || AsmOperand::parse("0", line);
+
+    let items = match name {
+        "PUSH" => {
+            expect(1)?;
+            vec![
+                instruction(
+                    instructions,
+                    "ADD",
+                    vec![
+                        AsmOperand::parse(operands[0], line)?,
+                        zero()?,
+                        AsmOperand::stack_top(),
+                    ],
+                    line,
+                )?,
+                instruction(
+                    instructions,
+                    "ARB",
+                    vec![AsmOperand::parse("1", line)?],
+                    line,
+                )?,
+            ]
+        }
+        "POP" => {
+            expect(1)?;
+            vec![
+                instruction(
+                    instructions,
+                    "ARB",
+                    vec![AsmOperand::parse("-1", line)?],
+                    line,
+                )?,
+                instruction(
+                    instructions,
+                    "ADD",
+                    vec![
+                        AsmOperand::stack_top(),
+                        zero()?,
+                        AsmOperand::parse(operands[0], line)?,
+                    ],
+                    line,
+                )?,
+            ]
+        }
+        "CALL" => {
+            expect(1)?;
+            // Push the address just past this expansion, which is 9 cells long
+            let return_address = (address + 9).to_string();
+            vec![
+                instruction(
+                    instructions,
+                    "ADD",
+                    vec![
+                        AsmOperand::parse(&return_address, line)?,
+                        zero()?,
+                        AsmOperand::stack_top(),
+                    ],
+                    line,
+                )?,
+                instruction(
+                    instructions,
+                    "ARB",
+                    vec![AsmOperand::parse("1", line)?],
+                    line,
+                )?,
+                instruction(
+                    instructions,
+                    "JNZ",
+                    vec![
+                        AsmOperand::parse("1", line)?,
+                        AsmOperand::parse(operands[0], line)?,
+                    ],
+                    line,
+                )?,
+            ]
+        }
+        "RET" => {
+            expect(0)?;
+            vec![
+                instruction(
+                    instructions,
+                    "ARB",
+                    vec![AsmOperand::parse("-1", line)?],
+                    line,
+                )?,
+                instruction(
+                    instructions,
+                    "JNZ",
+                    vec![AsmOperand::parse("1", line)?, AsmOperand::stack_top()],
+                    line,
+                )?,
+            ]
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(items))
+}
+
+fn is_label(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Mnemonics come from the instruction set, so custom opcodes can be assembled by name
+pub fn assemble<T: Register>(
+    instructions: &InstructionSet<T>,
+    source: &str,
+) -> Result<Vec<RegisterSize>, AsmError> {
+    let mut labels: HashMap<String, usize> = HashMap::new();
+    let mut items: Vec<(usize, Item)> = Vec::new();
+    let mut address = 0;
+
+    // First pass works out where everything lives, so labels can be used before they're defined
+    for (index, raw_line) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut text = raw_line.split(';').next().unwrap().trim();
+
+        while let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+
+            if !is_label(label) {
+                return Err(AsmError::new(line, format!("invalid label {:?}", label)));
+            }
+
+            if labels.insert(label.to_string(), address).is_some() {
+                return Err(AsmError::new(line, format!("duplicate label {:?}", label)));
+            }
+
+            text = text[colon + 1..].trim();
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let (name, rest) = match text.find(char::is_whitespace) {
+            Some(split) => (&text[..split], &text[split..]),
+            None => (text, ""),
+        };
+
+        let operands = split_operands(rest);
+        let name = name.to_ascii_uppercase();
+
+        let new_items = match name.as_str() {
+            ".DATA" => {
+                if operands.is_empty() {
+                    return Err(AsmError::new(
+                        line,
+                        ".data needs at least one value".to_string(),
+                    ));
+                }
+
+                vec![Item::Data(
+                    operands
+                        .iter()
+                        .map(|operand| Expression::parse(operand, line))
+                        .collect::<Result<Vec<Expression>, AsmError>>()?,
+                )]
+            }
+            ".ZERO" => {
+                let count = match operands.as_slice() {
+                    [count] => usize::from_str(count)
+                        .map_err(|_| AsmError::new(line, format!("invalid count {:?}", count)))?,
+                    _ => {
+                        return Err(AsmError::new(
+                            line,
+                            ".zero takes a single count".to_string(),
+                        ))
+                    }
+                };
+
+                if count > MAX_PROGRAM_SIZE {
+                    return Err(AsmError::new(line, format!("count {} is too large", count)));
+                }
+
+                vec![Item::Zero(count)]
+            }
+            _ => match expand_macro(instructions, &name, &operands, address, line)? {
+                Some(expanded) => expanded,
+                None => vec![instruction(
+                    instructions,
+                    &name,
+                    operands
+                        .iter()
+                        .map(|operand| AsmOperand::parse(operand, line))
+                        .collect::<Result<Vec<AsmOperand>, AsmError>>()?,
+                    line,
+                )?],
+            },
+        };
+
+        for item in new_items {
+            address = address
+                .checked_add(item.len())
+                .filter(|end| *end <= MAX_PROGRAM_SIZE)
+                .ok_or_else(|| AsmError::new(line, "program is too large".to_string()))?;
+            items.push((line, item));
+        }
+    }
+
+    let mut program = Vec::with_capacity(address);
+
+    for (line, item) in items {
+        match item {
+            Item::Instruction { opcode, operands } => {
+                let mut full_opcode = opcode;
+                let mut scale = 100;
+
+                for operand in &operands {
+                    full_opcode += scale
+                        * match operand.mode {
+                            ParameterMode::PositionMode => 0,
+                            ParameterMode::ImmediateMode => 1,
+                            ParameterMode::RelativeMode => 2,
+                        };
+                    scale *= 10;
+                }
+
+                program.push(full_opcode);
+
+                for operand in &operands {
+                    program.push(operand.expression.evaluate(&labels, line)?);
+                }
+            }
+            Item::Data(values) => {
+                for value in &values {
+                    program.push(value.evaluate(&labels, line)?);
+                }
+            }
+            Item::Zero(count) => program.extend(vec![0; count]),
+        }
+    }
+
+    Ok(program)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::disasm::disassemble;
+    use crate::intcode::{IntCodeInterpreter, IsaLevel, Operation, StepResult};
+
+    fn standard() -> InstructionSet {
+        InstructionSet::standard()
+    }
+
+    #[test]
+    fn test_basic_instructions() {
+        assert_eq!(
+            assemble(&standard(), "MUL [4], 3, [4]\n.data 33").unwrap(),
+            vec![1002, 4, 3, 4, 33]
+        );
+        assert_eq!(
+            assemble(&standard(), "add [rb+1], [rb-2], [rb]\nhlt").unwrap(),
+            vec![22201, 1, -2, 0, 99]
+        );
+    }
+
+    #[test]
+    fn test_labels_and_named_data() {
+        let source = "
+            ; Count down from three, printing as we go
+            loop:   OUT [counter]
+                    ADD [counter], -1, [counter]
+                    JNZ [counter], loop
+                    HLT
+            counter: .data 3
+        ";
+
+        let program = assemble(&standard(), source).unwrap();
+        assert_eq!(program, vec![4, 10, 1001, 10, -1, 10, 1005, 10, 0, 99, 3]);
+
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(&program);
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.drain_outputs(), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn test_label_expressions_and_zero() {
+        let program = assemble(
+            &standard(),
+            "start: JNZ 1, end-1\n.zero 2\nend: .data start+5, -end",
+        )
+        .unwrap();
+        assert_eq!(program, vec![1105, 1, 4, 0, 0, 5, -5]);
+    }
+
+    #[test]
+    fn test_call_and_return() {
+        let source = "
+                    ARB stack
+                    PUSH 20
+                    CALL double
+                    POP [result]
+                    OUT [result]
+                    HLT
+
+            ; Doubles the argument in place, just below the return address
+            double: MUL [rb-2], 2, [rb-2]
+                    RET
+
+            result: .data 0
+            stack:  .zero 8
+        ";
+
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(&assemble(&standard(), source).unwrap());
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.drain_outputs(), vec![40]);
+    }
+
+    #[test]
+    fn test_instruction_sets() {
+        let mut set = standard();
+        set.register(
+            50,
+            Operation::new("SWAP", 2, &[0, 1], |_| Ok(StepResult::Continue)),
+        );
+
+        assert_eq!(
+            assemble(&set, "swap [1], [rb-2]").unwrap(),
+            vec![2050, 1, -2]
+        );
+        assert_eq!(
+            assemble(&set, "SWAP [1], 2").unwrap_err().message,
+            "SWAP can't write to an immediate operand"
+        );
+
+        // Day 2 machines had no relative base to keep a stack in
+        let day2: InstructionSet = InstructionSet::for_level(IsaLevel::Day2);
+        assert_eq!(
+            assemble(&day2, "PUSH 1").unwrap_err().message,
+            "unknown instruction \"ARB\""
+        );
+    }
+
+    #[test]
+    fn test_disassembly_round_trip() {
+        let program = vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+        let source = disassemble(&standard(), &program)
+            .lines
+            .iter()
+            .map(|line| line.to_string()[6..].to_string())
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        assert_eq!(assemble(&standard(), &source).unwrap(), program);
+    }
+
+    #[test]
+    fn test_errors_point_at_lines() {
+        assert_eq!(
+            assemble(&standard(), "HLT\nFOO 1").unwrap_err(),
+            AsmError::new(2, "unknown instruction \"FOO\"".to_string())
+        );
+        assert_eq!(
+            assemble(&standard(), "\n\nJNZ 1, nowhere")
+                .unwrap_err()
+                .to_string(),
+            "line 3: undefined label \"nowhere\""
+        );
+        assert_eq!(assemble(&standard(), "ADD 1, 2, 3").unwrap_err().line, 1);
+        assert_eq!(
+            assemble(&standard(), "OUT 1, 2").unwrap_err().message,
+            "OUT expects 1 operand(s), found 2"
+        );
+        assert_eq!(
+            assemble(&standard(), "a: HLT\na: HLT")
+                .unwrap_err()
+                .to_string(),
+            "line 2: duplicate label \"a\""
+        );
+        assert_eq!(
+            assemble(&standard(), "HLT\n.data 9223372036854775807+1")
+                .unwrap_err()
+                .to_string(),
+            "line 2: value out of range"
+        );
+        assert_eq!(
+            assemble(&standard(), ".zero 18446744073709551615\nHLT")
+                .unwrap_err()
+                .to_string(),
+            "line 1: count 18446744073709551615 is too large"
+        );
+        assert_eq!(
+            assemble(&standard(), ".zero 16777215\n.zero 16777215")
+                .unwrap_err()
+                .to_string(),
+            "line 2: program is too large"
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use super::disasm::{disassemble, disassemble_from, Line, Listing};
+use super::{InstructionSet, Register, RegisterSize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+
+// How many times each address was executed as an instruction
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Coverage {
+    pub hits: BTreeMap<usize, u64>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage::default()
+    }
+
+    pub(crate) fn record(&mut self, address: usize) {
+        *self.hits.entry(address).or_insert(0) += 1;
+    }
+
+    // Adds another run's hits to these
+    pub fn merge(&mut self, other: &Coverage) {
+        for (address, count) in &other.hits {
+            *self.hits.entry(*address).or_insert(0) += count;
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Coverage> {
+        Coverage::from_str(&fs::read_to_string(path)?)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    pub fn hits(&self, address: usize) -> u64 {
+        self.hits.get(&address).copied().unwrap_or(0)
+    }
+
+    // Addresses that were executed are used as extra entry points, so code only reachable through
+    // dynamic jumps still shows up as code rather than data
+    pub fn listing<T: Register>(
+        &self,
+        instructions: &InstructionSet<T>,
+        program: &[RegisterSize],
+    ) -> Listing {
+        let mut entry_points = self.hits.keys().copied().collect::<Vec<usize>>();
+        entry_points.extend(
+            disassemble(instructions, program)
+                .instructions()
+                .map(|i| i.address),
+        );
+
+        disassemble_from(instructions, program, &entry_points)
+    }
+
+    // Runs of instructions in the listing that were never executed
+    pub fn unexecuted_regions<T: Register>(
+        &self,
+        instructions: &InstructionSet<T>,
+        program: &[RegisterSize],
+    ) -> Vec<Range<usize>> {
+        let mut regions: Vec<Range<usize>> = Vec::new();
+
+        for instruction in self.listing(instructions, program).instructions() {
+            if self.hits(instruction.address) > 0 {
+                continue;
+            }
+
+            let end = instruction.address + instruction.len();
+
+            match regions.last_mut() {
+                Some(region) if region.end == instruction.address => region.end = end,
+                _ => regions.push(instruction.address..end),
+            }
+        }
+
+        regions
+    }
+
+    // The disassembly with hit counts down the side, followed by a summary
+    pub fn report<T: Register>(
+        &self,
+        instructions: &InstructionSet<T>,
+        program: &[RegisterSize],
+    ) -> String {
+        let listing = self.listing(instructions, program);
+        let mut report = String::new();
+        let mut total = 0;
+        let mut covered = 0;
+
+        for line in &listing.lines {
+            match line {
+                Line::Instruction(instruction) => {
+                    let hits = self.hits(instruction.address);
+                    total += 1;
+
+                    if hits > 0 {
+                        covered += 1;
+                        write!(report, "{:>10}  ", hits).unwrap();
+                    } else {
+                        write!(report, "{:>10}  ", "-").unwrap();
+                    }
+                }
+                Line::Data { .. } => write!(report, "{:>10}  ", "").unwrap(),
+            }
+
+            writeln!(report, "{}", line).unwrap();
+        }
+
+        writeln!(
+            report,
+            "\n{} of {} instructions executed ({:.1}%)",
+            covered,
+            total,
+            if total == 0 {
+                0.0
+            } else {
+                covered as f64 * 100.0 / total as f64
+            }
+        )
+        .unwrap();
+
+        for region in self.unexecuted_regions(instructions, program) {
+            writeln!(report, "never executed: {}..{}", region.start, region.end).unwrap();
+        }
+
+        report
+    }
+}
+
+// One "address count" pair per line, which is easy to merge and diff
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (address, count) in &self.hits {
+            writeln!(f, "{} {}", address, count)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Coverage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut coverage = Coverage::new();
+
+        for (number, line) in s.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace().map(u64::from_str);
+
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(Ok(address)), Some(Ok(count)), None) => {
+                    *coverage.hits.entry(address as usize).or_insert(0) += count
+                }
+                _ => {
+                    return Err(format!(
+                        "line {}: expected an address and a count",
+                        number + 1
+                    ))
+                }
+            }
+        }
+
+        Ok(coverage)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::IntCodeInterpreter;
+
+    // Outputs 1 if the input is positive and 0 otherwise
+    const SIGN: [RegisterSize; 13] = [3, 12, 1007, 12, 1, 12, 1005, 12, 11, 104, 1, 99, 0];
+
+    fn standard() -> InstructionSet {
+        InstructionSet::standard()
+    }
+
+    fn run_with_input(input: RegisterSize) -> Coverage {
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.set_coverage(true);
+        interpreter.reset(&SIGN);
+        interpreter.add_input(input);
+        interpreter.run().unwrap();
+
+        interpreter.take_coverage().unwrap()
+    }
+
+    #[test]
+    fn test_collection_and_merging() {
+        let negative = run_with_input(-5);
+        assert_eq!(
+            negative.hits.keys().copied().collect::<Vec<usize>>(),
+            vec![0, 2, 6, 11]
+        );
+        assert_eq!(negative.unexecuted_regions(&standard(), &SIGN), vec![9..11]);
+
+        let mut merged = run_with_input(7);
+        merged.merge(&negative);
+        merged.merge(&negative);
+        assert_eq!(merged.hits(0), 3);
+        assert_eq!(merged.hits(9), 1);
+        assert!(merged.unexecuted_regions(&standard(), &SIGN).is_empty());
+    }
+
+    #[test]
+    fn test_report() {
+        let report = run_with_input(-5).report(&standard(), &SIGN);
+
+        assert!(report.starts_with("         1  0000  IN [12]\n"));
+        assert!(report.contains("\n         -  0009  OUT 1\n"));
+        assert!(report.contains("\n            0012  .data 0\n"));
+        assert!(report.ends_with("4 of 5 instructions executed (80.0%)\nnever executed: 9..11\n"));
+    }
+
+    #[test]
+    fn test_text_format() {
+        let coverage = run_with_input(3);
+        let text = coverage.to_string();
+        assert!(text.starts_with("0 1\n2 1\n"));
+
+        assert_eq!(Coverage::from_str(&text), Ok(coverage));
+        assert!(Coverage::from_str("12").is_err());
+    }
+}
@@ -0,0 +1,491 @@
+use super::disasm::{decode, Instruction};
+use super::{InstructionSet, IntCodeInterpreter, IntcodeError, RegisterSize, StepResult};
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StopReason {
+    Stepped,
+    Breakpoint(usize),
+    OpcodeBreakpoint {
+        address: usize,
+        opcode: RegisterSize,
+    },
+    Watchpoint {
+        address: usize,
+        old: RegisterSize,
+        new: RegisterSize,
+    },
+    NeedsInput,
+    Halted,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Command {
+    Break(usize),
+    BreakOpcode(RegisterSize),
+    Delete(usize),
+    DeleteOpcode(RegisterSize),
+    Watch(usize),
+    Unwatch(usize),
+    Step(usize),
+    Next,
+    Continue,
+    Print(usize, usize),
+    Registers,
+    Input(Vec<RegisterSize>),
+    Output,
+    Help,
+    Quit,
+}
+
+// Opcodes can be given either as a number or as a mnemonic from the machine's instruction set
+fn parse_opcode(text: &str, instructions: &InstructionSet) -> Result<RegisterSize, String> {
+    if let Ok(opcode) = RegisterSize::from_str(text) {
+        return Ok(opcode);
+    }
+
+    instructions
+        .find(text)
+        .map(|opcode| opcode as RegisterSize)
+        .ok_or_else(|| format!("unknown opcode {:?}", text))
+}
+
+fn parse_number<T: FromStr>(text: Option<&str>, what: &str) -> Result<T, String> {
+    match text {
+        Some(text) => T::from_str(text).map_err(|_| format!("invalid {} {:?}", what, text)),
+        None => Err(format!("missing {}", what)),
+    }
+}
+
+impl Command {
+    pub fn parse(line: &str, instructions: &InstructionSet) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or("");
+        let first = words.next();
+        let second = words.next();
+
+        match (name, first) {
+            ("break" | "b", Some("op")) => Ok(Command::BreakOpcode(parse_opcode(
+                second.ok_or("missing opcode")?,
+                instructions,
+            )?)),
+            ("break" | "b", _) => Ok(Command::Break(parse_number(first, "address")?)),
+            ("delete" | "d", Some("op")) => Ok(Command::DeleteOpcode(parse_opcode(
+                second.ok_or("missing opcode")?,
+                instructions,
+            )?)),
+            ("delete" | "d", _) => Ok(Command::Delete(parse_number(first, "address")?)),
+            ("watch" | "w", _) => Ok(Command::Watch(parse_number(first, "address")?)),
+            ("unwatch", _) => Ok(Command::Unwatch(parse_number(first, "address")?)),
+            ("step" | "s", None) => Ok(Command::Step(1)),
+            ("step" | "s", _) => Ok(Command::Step(parse_number(first, "count")?)),
+            ("next" | "n", _) => Ok(Command::Next),
+            ("continue" | "c", _) => Ok(Command::Continue),
+            ("print" | "p", _) => {
+                let start = parse_number(first, "address")?;
+                let end = match second {
+                    Some(_) => parse_number(second, "address")?,
+                    None => start,
+                };
+
+                if end < start {
+                    return Err("end of range comes before the start".to_string());
+                }
+
+                Ok(Command::Print(start, end))
+            }
+            ("regs" | "rb" | "r", _) => Ok(Command::Registers),
+            ("input" | "i", _) => Ok(Command::Input(
+                line.split_whitespace()
+                    .skip(1)
+                    .map(|value| parse_number(Some(value), "input"))
+                    .collect::<Result<Vec<RegisterSize>, String>>()?,
+            )),
+            ("output" | "o", _) => Ok(Command::Output),
+            ("help" | "h", _) => Ok(Command::Help),
+            ("quit" | "q", _) => Ok(Command::Quit),
+            _ => Err(format!("unknown command {:?}, try help", name)),
+        }
+    }
+}
+
+const HELP: &str = "\
+break <addr> | break op <opcode>    stop before executing an address or opcode
+delete <addr> | delete op <opcode>  remove a breakpoint
+watch <addr> | unwatch <addr>       stop when a memory cell changes
+step [n]                            execute n instructions
+next                                run until the following instruction is reached
+continue                            run until something stops the machine
+print <start> [end]                 show memory, inclusive
+regs                                show the instruction pointer and relative base
+input <values...>                   queue input values
+output                              show the pending output queue
+quit";
+
+pub struct Debugger {
+    interpreter: IntCodeInterpreter,
+    breakpoints: BTreeSet<usize>,
+    opcode_breakpoints: BTreeSet<RegisterSize>,
+    watchpoints: BTreeMap<usize, RegisterSize>,
+}
+
+impl Debugger {
+    pub fn new(program: &[RegisterSize]) -> Self {
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(program);
+
+        Debugger {
+            interpreter,
+            breakpoints: BTreeSet::new(),
+            opcode_breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+        }
+    }
+
+    pub fn interpreter(&self) -> &IntCodeInterpreter {
+        &self.interpreter
+    }
+
+    pub fn interpreter_mut(&mut self) -> &mut IntCodeInterpreter {
+        &mut self.interpreter
+    }
+
+    pub fn add_breakpoint(&mut self, address: usize) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    // Matches on the opcode alone, whatever the parameter modes
+    pub fn add_opcode_breakpoint(&mut self, opcode: RegisterSize) {
+        self.opcode_breakpoints.insert(opcode % 100);
+    }
+
+    pub fn remove_opcode_breakpoint(&mut self, opcode: RegisterSize) -> bool {
+        self.opcode_breakpoints.remove(&(opcode % 100))
+    }
+
+    pub fn add_watchpoint(&mut self, address: usize) {
+        let value = self.interpreter.memory().read(address);
+        self.watchpoints.insert(address, value);
+    }
+
+    pub fn remove_watchpoint(&mut self, address: usize) -> bool {
+        self.watchpoints.remove(&address).is_some()
+    }
+
+    pub fn current_instruction(&self) -> Option<Instruction> {
+        let address = self.interpreter.instruction_pointer();
+        let cells = (address..address + 4)
+            .map(|cell| self.interpreter.memory().read(cell))
+            .collect::<Vec<RegisterSize>>();
+
+        decode(self.interpreter.instruction_set(), &cells, 0).map(|instruction| Instruction {
+            address,
+            ..instruction
+        })
+    }
+
+    fn check_breakpoints(&self) -> Option<StopReason> {
+        let address = self.interpreter.instruction_pointer();
+        let opcode = self.interpreter.memory().read(address);
+
+        if self.breakpoints.contains(&address) {
+            Some(StopReason::Breakpoint(address))
+        } else if self.opcode_breakpoints.contains(&(opcode % 100)) {
+            Some(StopReason::OpcodeBreakpoint { address, opcode })
+        } else {
+            None
+        }
+    }
+
+    fn check_watchpoints(&mut self) -> Option<StopReason> {
+        let memory = self.interpreter.memory();
+
+        for (address, old) in self.watchpoints.iter_mut() {
+            let new = memory.read(*address);
+
+            if new != *old {
+                let reason = StopReason::Watchpoint {
+                    address: *address,
+                    old: *old,
+                    new,
+                };
+                *old = new;
+                return Some(reason);
+            }
+        }
+
+        None
+    }
+
+    pub fn step(&mut self) -> Result<StopReason, IntcodeError> {
+        match self.interpreter.step()? {
+            StepResult::NeedsInput => Ok(StopReason::NeedsInput),
+            StepResult::Halted => Ok(StopReason::Halted),
+            _ => Ok(self.check_watchpoints().unwrap_or(StopReason::Stepped)),
+        }
+    }
+
+    fn run_to(&mut self, target: Option<usize>) -> Result<StopReason, IntcodeError> {
+        // Always execute at least one instruction, so we can continue from a breakpoint
+        let mut reason = self.step()?;
+
+        while reason == StopReason::Stepped {
+            if Some(self.interpreter.instruction_pointer()) == target {
+                break;
+            }
+
+            if let Some(breakpoint) = self.check_breakpoints() {
+                return Ok(breakpoint);
+            }
+
+            reason = self.step()?;
+        }
+
+        Ok(reason)
+    }
+
+    // Steps over whatever the current instruction jumps into, as long as it comes back
+    pub fn step_over(&mut self) -> Result<StopReason, IntcodeError> {
+        let target = self
+            .current_instruction()
+            .map(|instruction| instruction.address + instruction.len());
+
+        self.run_to(target)
+    }
+
+    pub fn continue_running(&mut self) -> Result<StopReason, IntcodeError> {
+        self.run_to(None)
+    }
+
+    fn describe_location(&self) -> String {
+        match self.current_instruction() {
+            Some(instruction) => format!("{:04}  {}", instruction.address, instruction),
+            None => format!(
+                "{:04}  .data {}",
+                self.interpreter.instruction_pointer(),
+                self.interpreter
+                    .memory()
+                    .read(self.interpreter.instruction_pointer())
+            ),
+        }
+    }
+
+    fn describe_stop(&self, reason: StopReason) -> String {
+        let location = self.describe_location();
+
+        match reason {
+            StopReason::Stepped => location,
+            StopReason::Breakpoint(_) => format!("Breakpoint\n{}", location),
+            StopReason::OpcodeBreakpoint { opcode, .. } => {
+                format!("Opcode breakpoint on {}\n{}", opcode % 100, location)
+            }
+            StopReason::Watchpoint { address, old, new } => {
+                format!("Watchpoint {}: {} -> {}\n{}", address, old, new, location)
+            }
+            StopReason::NeedsInput => format!("Waiting for input\n{}", location),
+            StopReason::Halted => "Program halted".to_string(),
+        }
+    }
+
+    fn join_values<'a, I: Iterator<Item = &'a RegisterSize>>(values: I) -> String {
+        values
+            .map(|value| value.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    // Runs a single command, returning the text to show the user
+    pub fn execute(&mut self, command: &Command) -> String {
+        let result = match command {
+            Command::Break(address) => {
+                self.add_breakpoint(*address);
+                Ok(format!("Breakpoint set at {}", address))
+            }
+            Command::BreakOpcode(opcode) => {
+                self.add_opcode_breakpoint(*opcode);
+                Ok(format!("Breakpoint set on opcode {}", opcode % 100))
+            }
+            Command::Delete(address) => Ok(if self.remove_breakpoint(*address) {
+                format!("Breakpoint at {} removed", address)
+            } else {
+                format!("No breakpoint at {}", address)
+            }),
+            Command::DeleteOpcode(opcode) => Ok(if self.remove_opcode_breakpoint(*opcode) {
+                format!("Breakpoint on opcode {} removed", opcode % 100)
+            } else {
+                format!("No breakpoint on opcode {}", opcode % 100)
+            }),
+            Command::Watch(address) => {
+                self.add_watchpoint(*address);
+                Ok(format!("Watching {}", address))
+            }
+            Command::Unwatch(address) => Ok(if self.remove_watchpoint(*address) {
+                format!("No longer watching {}", address)
+            } else {
+                format!("Not watching {}", address)
+            }),
+            Command::Step(count) => {
+                let mut reason = Ok(StopReason::Stepped);
+
+                for _ in 0..*count {
+                    reason = self.step();
+
+                    if reason != Ok(StopReason::Stepped) {
+                        break;
+                    }
+                }
+
+                reason.map(|reason| self.describe_stop(reason))
+            }
+            Command::Next => self.step_over().map(|reason| self.describe_stop(reason)),
+            Command::Continue => self
+                .continue_running()
+                .map(|reason| self.describe_stop(reason)),
+            Command::Print(start, end) => Ok((*start..=*end)
+                .map(|address| {
+                    format!(
+                        "{:04}  {}",
+                        address,
+                        self.interpreter.memory().read(address)
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n")),
+            Command::Registers => Ok(format!(
+                "ip {}\nrb {}\n{}",
+                self.interpreter.instruction_pointer(),
+                self.interpreter.relative_offset(),
+                self.describe_location()
+            )),
+            Command::Input(values) => {
+                for value in values {
+                    self.interpreter.add_input(*value);
+                }
+
+                Ok(format!(
+                    "Pending inputs: {}",
+                    Debugger::join_values(self.interpreter.pending_inputs().iter())
+                ))
+            }
+            Command::Output => Ok(format!(
+                "Pending outputs: {}",
+                Debugger::join_values(self.interpreter.outputs().iter())
+            )),
+            Command::Help => Ok(HELP.to_string()),
+            Command::Quit => Ok(String::new()),
+        };
+
+        result.unwrap_or_else(|error| format!("Error: {}", error))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::Operation;
+
+    // Counts a cell at 13 down from 3, printing each value
+    const COUNTDOWN: [RegisterSize; 14] = [4, 13, 1001, 13, -1, 13, 1005, 13, 0, 99, 0, 0, 0, 3];
+
+    #[test]
+    fn test_parse_commands() {
+        let mut set: InstructionSet = InstructionSet::standard();
+        let parse = |line: &str, set: &InstructionSet| Command::parse(line, set);
+
+        assert_eq!(parse("b 12", &set), Ok(Command::Break(12)));
+        assert_eq!(parse("break op out", &set), Ok(Command::BreakOpcode(4)));
+        assert_eq!(parse("s", &set), Ok(Command::Step(1)));
+        assert_eq!(parse("p 4 8", &set), Ok(Command::Print(4, 8)));
+        assert_eq!(parse("input 1 -2", &set), Ok(Command::Input(vec![1, -2])));
+        assert!(parse("p 8 4", &set).is_err());
+        assert!(parse("frobnicate", &set).is_err());
+
+        set.register(
+            50,
+            Operation::new("SWAP", 2, &[0, 1], |_| Ok(StepResult::Continue)),
+        );
+        assert_eq!(parse("d op swap", &set), Ok(Command::DeleteOpcode(50)));
+    }
+
+    #[test]
+    fn test_address_breakpoint() {
+        let mut debugger = Debugger::new(&COUNTDOWN);
+        debugger.add_breakpoint(6);
+
+        assert_eq!(debugger.continue_running(), Ok(StopReason::Breakpoint(6)));
+        assert_eq!(debugger.continue_running(), Ok(StopReason::Breakpoint(6)));
+        assert_eq!(debugger.interpreter().outputs().len(), 2);
+
+        debugger.remove_breakpoint(6);
+        assert_eq!(debugger.continue_running(), Ok(StopReason::Halted));
+        assert_eq!(debugger.interpreter_mut().drain_outputs(), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn test_opcode_breakpoint() {
+        let mut debugger = Debugger::new(&COUNTDOWN);
+        debugger.add_opcode_breakpoint(1001);
+
+        assert_eq!(
+            debugger.continue_running(),
+            Ok(StopReason::OpcodeBreakpoint {
+                address: 2,
+                opcode: 1001
+            })
+        );
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        let mut debugger = Debugger::new(&COUNTDOWN);
+        debugger.add_watchpoint(13);
+
+        assert_eq!(
+            debugger.continue_running(),
+            Ok(StopReason::Watchpoint {
+                address: 13,
+                old: 3,
+                new: 2
+            })
+        );
+        assert_eq!(debugger.interpreter().instruction_pointer(), 6);
+    }
+
+    #[test]
+    fn test_next_steps_over_subroutines() {
+        // Jump to a subroutine at 6 that outputs and jumps back to 3
+        let program = vec![1105, 1, 6, 99, 0, 0, 104, 7, 1105, 1, 3];
+        let mut debugger = Debugger::new(&program);
+
+        assert_eq!(debugger.step_over(), Ok(StopReason::Stepped));
+        assert_eq!(debugger.interpreter().instruction_pointer(), 3);
+        assert_eq!(debugger.interpreter().outputs().len(), 1);
+    }
+
+    #[test]
+    fn test_execute_commands() {
+        let mut debugger = Debugger::new(&[3, 5, 4, 5, 99, 0]);
+
+        assert_eq!(
+            debugger.execute(&Command::Step(1)),
+            "Waiting for input\n0000  IN [5]"
+        );
+        assert_eq!(
+            debugger.execute(&Command::Input(vec![8])),
+            "Pending inputs: 8"
+        );
+        assert_eq!(debugger.execute(&Command::Step(2)), "0004  HLT");
+        assert_eq!(debugger.execute(&Command::Output), "Pending outputs: 8");
+        assert_eq!(debugger.execute(&Command::Print(4, 5)), "0004  99\n0005  8");
+        assert_eq!(
+            debugger.execute(&Command::Registers),
+            "ip 4\nrb 0\n0004  HLT"
+        );
+        assert_eq!(debugger.execute(&Command::Continue), "Program halted");
+    }
+}
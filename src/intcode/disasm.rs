@@ -0,0 +1,432 @@
+use super::{InstructionSet, ParameterMode, Register, RegisterSize};
+use std::collections::BTreeSet;
+use std::fmt;
+
+// Data regions are split into lines of at most this many values
+const DATA_LINE_WIDTH: usize = 8;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Operand {
+    pub mode: ParameterMode,
+    pub value: RegisterSize,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            ParameterMode::PositionMode => write!(f, "[{}]", self.value),
+            ParameterMode::ImmediateMode => write!(f, "{}", self.value),
+            ParameterMode::RelativeMode if self.value < 0 => write!(f, "[rb{}]", self.value),
+            ParameterMode::RelativeMode => write!(f, "[rb+{}]", self.value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Instruction {
+    pub address: usize,
+    pub opcode: RegisterSize,
+    pub mnemonic: String,
+    pub operands: Vec<Operand>,
+}
+
+impl Instruction {
+    pub fn len(&self) -> usize {
+        self.operands.len() + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic)?;
+
+        for (index, operand) in self.operands.iter().enumerate() {
+            write!(f, "{}{}", if index == 0 { " " } else { ", " }, operand)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Returns None if the cell at the address can't be executed as an instruction from the set
+pub fn decode<T: Register>(
+    instructions: &InstructionSet<T>,
+    program: &[RegisterSize],
+    address: usize,
+) -> Option<Instruction> {
+    let opcode = *program.get(address)?;
+    let operation = instructions.get(opcode)?;
+
+    let mut modes = opcode / 100;
+    let mut operands = Vec::with_capacity(operation.arity);
+
+    for index in 0..operation.arity {
+        let mode = match modes % 10 {
+            0 => ParameterMode::PositionMode,
+            1 => ParameterMode::ImmediateMode,
+            2 => ParameterMode::RelativeMode,
+            _ => return None,
+        };
+
+        if !instructions.allows_mode(mode)
+            || (mode == ParameterMode::ImmediateMode && operation.writes.contains(&index))
+        {
+            return None;
+        }
+
+        operands.push(Operand {
+            mode,
+            value: *program.get(address + index + 1)?,
+        });
+
+        modes /= 10;
+    }
+
+    // Any leftover mode digits mean this isn't really an instruction
+    if modes != 0 {
+        return None;
+    }
+
+    Some(Instruction {
+        address,
+        opcode,
+        mnemonic: operation.name.clone(),
+        operands,
+    })
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Line {
+    Instruction(Instruction),
+    // Cells never reached as code, only ever usable as data
+    Data {
+        address: usize,
+        values: Vec<RegisterSize>,
+    },
+}
+
+impl Line {
+    pub fn address(&self) -> usize {
+        match self {
+            Line::Instruction(instruction) => instruction.address,
+            Line::Data { address, .. } => *address,
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Line::Instruction(instruction) => {
+                write!(f, "{:04}  {}", instruction.address, instruction)
+            }
+            Line::Data { address, values } => write!(
+                f,
+                "{:04}  .data {}",
+                address,
+                values
+                    .iter()
+                    .map(|value| value.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Listing {
+    pub lines: Vec<Line>,
+    // Jumps whose target is only known at runtime, so code after them may be missed
+    pub dynamic_jumps: Vec<usize>,
+}
+
+impl Listing {
+    pub fn instructions(&self) -> impl Iterator<Item = &Instruction> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Instruction(instruction) => Some(instruction),
+            _ => None,
+        })
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            write!(f, "{}", line)?;
+
+            if let Line::Instruction(instruction) = line {
+                if self.dynamic_jumps.contains(&instruction.address) {
+                    write!(f, "    ; dynamic jump")?;
+                }
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn successors(instruction: &Instruction) -> (Vec<usize>, bool) {
+    let next = instruction.address + instruction.len();
+    let mut targets = Vec::new();
+    let mut dynamic = false;
+
+    // Only the standard control flow is understood, anything custom is assumed to carry on
+    match instruction.mnemonic.as_str() {
+        "HLT" => {}
+        "JNZ" | "JZ" => {
+            let condition = instruction.operands[0];
+            let target = instruction.operands[1];
+
+            let (may_jump, may_fall_through) = match condition.mode {
+                ParameterMode::ImmediateMode => {
+                    let taken = (condition.value != 0) == (instruction.mnemonic == "JNZ");
+                    (taken, !taken)
+                }
+                _ => (true, true),
+            };
+
+            if may_jump {
+                if target.mode == ParameterMode::ImmediateMode {
+                    if target.value >= 0 {
+                        targets.push(target.value as usize);
+                    }
+                } else {
+                    dynamic = true;
+                }
+            }
+
+            if may_fall_through {
+                targets.push(next);
+            }
+        }
+        _ => targets.push(next),
+    }
+
+    (targets, dynamic)
+}
+
+// Recursive descent from the given entry points, so only code that can actually be reached is
+// decoded and everything else is left as data
+pub fn disassemble_from<T: Register>(
+    instructions: &InstructionSet<T>,
+    program: &[RegisterSize],
+    entry_points: &[usize],
+) -> Listing {
+    let mut decoded = Vec::new();
+    let mut code_cells = vec![false; program.len()];
+    let mut dynamic_jumps = Vec::new();
+    let mut visited = BTreeSet::new();
+
+    // Entry points are explored one at a time, so earlier ones take priority if paths overlap
+    for entry_point in entry_points {
+        let mut pending = vec![*entry_point];
+
+        while let Some(address) = pending.pop() {
+            if !visited.insert(address) || address >= program.len() || code_cells[address] {
+                continue;
+            }
+
+            let instruction = match decode(instructions, program, address) {
+                Some(instruction) => instruction,
+                None => continue,
+            };
+
+            // Don't let a misaligned path overlap code we've already decoded
+            let cells = &mut code_cells[address..address + instruction.len()];
+
+            if cells.contains(&true) {
+                continue;
+            }
+
+            cells.fill(true);
+
+            let (targets, dynamic) = successors(&instruction);
+
+            if dynamic {
+                dynamic_jumps.push(address);
+            }
+
+            pending.extend(targets);
+            decoded.push(instruction);
+        }
+    }
+
+    decoded.sort_by_key(|instruction| instruction.address);
+    dynamic_jumps.sort_unstable();
+
+    let mut lines = Vec::new();
+    let mut decoded = decoded.into_iter().peekable();
+    let mut address = 0;
+
+    while address < program.len() {
+        if let Some(instruction) = decoded.next_if(|next| next.address == address) {
+            address += instruction.len();
+            lines.push(Line::Instruction(instruction));
+            continue;
+        }
+
+        let start = address;
+
+        while address < program.len() && !code_cells[address] && address - start < DATA_LINE_WIDTH {
+            address += 1;
+        }
+
+        lines.push(Line::Data {
+            address: start,
+            values: program[start..address].to_vec(),
+        });
+    }
+
+    Listing {
+        lines,
+        dynamic_jumps,
+    }
+}
+
+pub fn disassemble<T: Register>(
+    instructions: &InstructionSet<T>,
+    program: &[RegisterSize],
+) -> Listing {
+    let mut entry_points = vec![0];
+
+    // Dynamic jumps are usually returns, whose targets get stored with an ADD or MUL of
+    // immediate values, so keep trying those as extra entry points until nothing new turns up
+    loop {
+        let listing = disassemble_from(instructions, program, &entry_points);
+
+        if listing.dynamic_jumps.is_empty() {
+            return listing;
+        }
+
+        let previous_count = entry_points.len();
+
+        for instruction in listing.instructions() {
+            if instruction.mnemonic != "ADD" && instruction.mnemonic != "MUL" {
+                continue;
+            }
+
+            for operand in &instruction.operands[..2] {
+                if operand.mode == ParameterMode::ImmediateMode
+                    && operand.value > 0
+                    && (operand.value as usize) < program.len()
+                    && !entry_points.contains(&(operand.value as usize))
+                {
+                    entry_points.push(operand.value as usize);
+                }
+            }
+        }
+
+        if entry_points.len() == previous_count {
+            return listing;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::{IsaLevel, Operation, StepResult};
+
+    fn standard() -> InstructionSet {
+        InstructionSet::standard()
+    }
+
+    #[test]
+    fn test_decode_modes() {
+        let instruction = decode(&standard(), &[21002, 4, 3, -2], 0).unwrap();
+
+        assert_eq!(instruction.mnemonic, "MUL");
+        assert_eq!(instruction.to_string(), "MUL [4], 3, [rb-2]");
+        assert_eq!(
+            decode(&standard(), &[1101, 1, 2, 3], 0)
+                .unwrap()
+                .to_string(),
+            "ADD 1, 2, [3]"
+        );
+    }
+
+    #[test]
+    fn test_decode_rejects_invalid_instructions() {
+        assert_eq!(decode(&standard(), &[11101, 1, 2, 3], 0), None);
+        assert_eq!(decode(&standard(), &[301, 1, 2, 3], 0), None);
+        assert_eq!(decode(&standard(), &[42], 0), None);
+        assert_eq!(decode(&standard(), &[1, 2], 0), None);
+
+        // Neither relative mode nor ARB existed yet on day 5
+        let day5: InstructionSet = InstructionSet::for_level(IsaLevel::Day5);
+        assert_eq!(decode(&day5, &[201, 1, 2, 3], 0), None);
+        assert_eq!(decode(&day5, &[109, 1], 0), None);
+    }
+
+    #[test]
+    fn test_custom_opcodes() {
+        let mut set = standard();
+        set.register(
+            50,
+            Operation::new("SWAP", 2, &[0, 1], |_| Ok(StepResult::Continue)),
+        );
+
+        assert_eq!(
+            disassemble(&set, &[2050, 4, -1, 99, 0]).to_string(),
+            "0000  SWAP [4], [rb-1]\n\
+             0003  HLT\n\
+             0004  .data 0\n"
+        );
+        assert_eq!(decode(&set, &[1050, 4, 5], 0), None);
+    }
+
+    #[test]
+    fn test_data_separation() {
+        let listing = disassemble(&standard(), &[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+
+        assert_eq!(
+            listing.to_string(),
+            "0000  ADD [9], [10], [3]\n\
+             0004  MUL [3], [11], [0]\n\
+             0008  HLT\n\
+             0009  .data 30, 40, 50\n"
+        );
+    }
+
+    #[test]
+    fn test_unreachable_code_is_data() {
+        // The jump always skips over the middle, so it should never be decoded
+        let listing = disassemble(&standard(), &[1105, 1, 7, 104, 1, 104, 2, 99]);
+
+        assert_eq!(
+            listing.lines,
+            vec![
+                Line::Instruction(decode(&standard(), &[1105, 1, 7], 0).unwrap()),
+                Line::Data {
+                    address: 3,
+                    values: vec![104, 1, 104, 2],
+                },
+                Line::Instruction(Instruction {
+                    address: 7,
+                    opcode: 99,
+                    mnemonic: "HLT".to_string(),
+                    operands: vec![],
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dynamic_jump_return_addresses() {
+        // Push a return address, call a subroutine, then return through the relative base
+        let program = vec![21101, 0, 9, 0, 1105, 1, 10, 0, 0, 99, 2105, 1, 0];
+        let listing = disassemble(&standard(), &program);
+
+        assert_eq!(listing.dynamic_jumps, vec![10]);
+        assert!(listing
+            .instructions()
+            .any(|instruction| instruction.address == 9 && instruction.mnemonic == "HLT"));
+    }
+}
@@ -0,0 +1,596 @@
+use super::ascii::{AsciiConsole, ConsoleError, ConsoleOutput};
+use super::{RegisterSize, StepResult};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+// Instructions an expect gets to see its pattern, unless a timeout says otherwise
+const DEFAULT_TIMEOUT: u64 = 1_000_000;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ExpectError {
+    // What was printed that didn't match is kept, as it's usually what went wrong
+    Timeout {
+        instructions: u64,
+        unmatched: String,
+    },
+    Halted {
+        unmatched: String,
+    },
+    // The pattern can't turn up until something else is sent
+    AwaitingInput {
+        unmatched: String,
+    },
+    Console(ConsoleError),
+}
+
+impl fmt::Display for ExpectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExpectError::Timeout {
+                instructions,
+                unmatched,
+            } => write!(
+                f,
+                "nothing matched after {} instructions, with {:?} unmatched",
+                instructions, unmatched
+            ),
+            ExpectError::Halted { unmatched } => {
+                write!(f, "halted with {:?} unmatched", unmatched)
+            }
+            ExpectError::AwaitingInput { unmatched } => {
+                write!(f, "waiting for input with {:?} unmatched", unmatched)
+            }
+            ExpectError::Console(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for ExpectError {}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Match {
+    // Which of the patterns matched
+    pub pattern: usize,
+    // Everything printed between the previous match and this one
+    pub before: String,
+}
+
+// Waits for text from a program before answering it. Text is only ever matched once, so each
+// expect picks up where the last one finished
+pub struct Session {
+    console: AsciiConsole,
+    unmatched: String,
+    // How much of the console's unfinished line has already been matched
+    partial_matched: usize,
+    values: Vec<RegisterSize>,
+    timeout: u64,
+}
+
+impl Session {
+    pub fn new(program: &[RegisterSize]) -> Self {
+        Session::from_console(AsciiConsole::new(program))
+    }
+
+    pub fn from_console(console: AsciiConsole) -> Self {
+        Session {
+            console,
+            unmatched: String::new(),
+            partial_matched: 0,
+            values: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub fn console(&self) -> &AsciiConsole {
+        &self.console
+    }
+
+    pub fn console_mut(&mut self) -> &mut AsciiConsole {
+        &mut self.console
+    }
+
+    // In instructions, counted separately for each expect
+    pub fn set_timeout(&mut self, instructions: u64) {
+        self.timeout = instructions;
+    }
+
+    pub fn timeout(&self) -> u64 {
+        self.timeout
+    }
+
+    pub fn send_line(&mut self, text: &str) -> Result<(), ExpectError> {
+        self.console.send_line(text).map_err(ExpectError::Console)
+    }
+
+    // Values too big to be characters, which can't be matched as text
+    pub fn take_values(&mut self) -> Vec<RegisterSize> {
+        std::mem::take(&mut self.values)
+    }
+
+    // Text printed since the last match
+    pub fn unmatched(&self) -> String {
+        let partial = self.console.partial_line();
+        format!(
+            "{}{}",
+            self.unmatched,
+            &partial[self.partial_matched.min(partial.len())..]
+        )
+    }
+
+    pub fn expect(&mut self, pattern: &str) -> Result<String, ExpectError> {
+        self.expect_any(&[pattern]).map(|found| found.before)
+    }
+
+    // Runs until one of the patterns shows up. When several do, the earliest in the text wins
+    pub fn expect_any(&mut self, patterns: &[&str]) -> Result<Match, ExpectError> {
+        let mut instructions = 0;
+
+        loop {
+            if let Some(found) = self._find(patterns) {
+                return Ok(found);
+            }
+
+            // Only output can bring a match, so there's no need to look again until there is some
+            loop {
+                if instructions >= self.timeout {
+                    return Err(ExpectError::Timeout {
+                        instructions,
+                        unmatched: self.unmatched(),
+                    });
+                }
+
+                let result = self
+                    .console
+                    .interpreter_mut()
+                    .step()
+                    .map_err(|error| ExpectError::Console(ConsoleError::Intcode(error)))?;
+                instructions += 1;
+
+                match result {
+                    StepResult::Output(_) => break,
+                    StepResult::NeedsInput => {
+                        return Err(ExpectError::AwaitingInput {
+                            unmatched: self.unmatched(),
+                        })
+                    }
+                    StepResult::Halted => {
+                        return Err(ExpectError::Halted {
+                            unmatched: self.unmatched(),
+                        })
+                    }
+                    _ => {}
+                }
+            }
+
+            self._collect();
+        }
+    }
+
+    fn _collect(&mut self) {
+        self.console.collect_output();
+
+        while let Some(output) = self.console.take_output() {
+            match output {
+                ConsoleOutput::Line(line) => {
+                    self.unmatched
+                        .push_str(&line[self.partial_matched.min(line.len())..]);
+                    self.unmatched.push('\n');
+                    self.partial_matched = 0;
+                }
+                ConsoleOutput::Value(value) => self.values.push(value),
+            }
+        }
+    }
+
+    fn _find(&mut self, patterns: &[&str]) -> Option<Match> {
+        let text = self.unmatched();
+
+        let (start, pattern) = patterns
+            .iter()
+            .enumerate()
+            .filter_map(|(index, pattern)| text.find(pattern).map(|start| (start, index)))
+            .min()?;
+
+        let end = start + patterns[pattern].len();
+
+        if end <= self.unmatched.len() {
+            self.unmatched.drain(..end);
+        } else {
+            self.partial_matched += end - self.unmatched.len();
+            self.unmatched.clear();
+        }
+
+        Some(Match {
+            pattern,
+            before: text[..start].to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Alternative {
+    Text(String),
+    Timeout,
+    Halted,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Command {
+    Timeout(u64),
+    // Alternatives without a label carry on with the next command
+    Expect(Vec<(Alternative, Option<usize>)>),
+    Send(String),
+    Label,
+    Goto(usize),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ScriptError {}
+
+// One command per line, with # starting a comment:
+//
+//   timeout 50000                             instructions each expect can take from here on
+//   expect "Command?"                         waits for the text
+//   expect "north" goto n "south" goto s      carries on at the label after whichever came first
+//   expect "Bye" timeout goto slow halt       timeout and halt can be branched on like text too
+//   send "take coin"                          sends the line
+//   label n
+//   goto n
+//
+// Text goes in double quotes, with \" \\ and \n escapes
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Script {
+    // Each with the line it came from
+    commands: Vec<(usize, Command)>,
+}
+
+impl Script {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Script> {
+        Script::from_str(&fs::read_to_string(path)?)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    pub fn run(&self, session: &mut Session) -> Result<(), ScriptError> {
+        let mut index = 0;
+
+        while let Some((line, command)) = self.commands.get(index) {
+            let failed = |error: ExpectError| ScriptError {
+                line: *line,
+                message: error.to_string(),
+            };
+
+            index += 1;
+
+            match command {
+                Command::Timeout(instructions) => session.set_timeout(*instructions),
+                Command::Send(text) => session.send_line(text).map_err(failed)?,
+                Command::Label => {}
+                Command::Goto(target) => index = *target,
+                Command::Expect(alternatives) => {
+                    let patterns = alternatives
+                        .iter()
+                        .filter_map(|(alternative, _)| match alternative {
+                            Alternative::Text(text) => Some(text.as_str()),
+                            _ => None,
+                        })
+                        .collect::<Vec<&str>>();
+
+                    let seen = match session.expect_any(&patterns) {
+                        Ok(found) => Alternative::Text(patterns[found.pattern].to_string()),
+                        Err(ExpectError::Timeout { .. })
+                            if alternatives.iter().any(|a| a.0 == Alternative::Timeout) =>
+                        {
+                            Alternative::Timeout
+                        }
+                        Err(ExpectError::Halted { .. })
+                            if alternatives.iter().any(|a| a.0 == Alternative::Halted) =>
+                        {
+                            Alternative::Halted
+                        }
+                        Err(error) => return Err(failed(error)),
+                    };
+
+                    let target = alternatives
+                        .iter()
+                        .find(|(alternative, _)| *alternative == seen)
+                        .and_then(|(_, target)| *target);
+
+                    if let Some(target) = target {
+                        index = target;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn tokenise(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        } else if c == '#' {
+            break;
+        } else if c == '"' {
+            let mut text = String::new();
+
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => text.push('\n'),
+                        Some(escaped @ '"') | Some(escaped @ '\\') => text.push(escaped),
+                        Some(other) => return Err(format!("unknown escape \\{}", other)),
+                        None => return Err("unterminated text".to_string()),
+                    },
+                    Some(c) => text.push(c),
+                    None => return Err("unterminated text".to_string()),
+                }
+            }
+
+            tokens.push(Token::Text(text));
+        } else {
+            let mut word = c.to_string();
+
+            while let Some(c) = chars.peek().filter(|c| !c.is_whitespace() && **c != '"') {
+                word.push(*c);
+                chars.next();
+            }
+
+            tokens.push(Token::Word(word));
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+}
+
+// Labels can be used before they're defined, so gotos are resolved once everything is read
+enum Parsed {
+    Command(Command),
+    Expect(Vec<(Alternative, Option<String>)>),
+    Goto(String),
+}
+
+fn parse_line(tokens: &[Token]) -> Result<Parsed, String> {
+    let word = |token: Option<&Token>| match token {
+        Some(Token::Word(word)) => Some(word.clone()),
+        _ => None,
+    };
+
+    let command = word(tokens.first()).ok_or("expected a command")?;
+    let arguments = &tokens[1..];
+
+    match (command.as_str(), arguments) {
+        ("timeout", [Token::Word(count)]) => count
+            .parse()
+            .map(|count| Parsed::Command(Command::Timeout(count)))
+            .map_err(|_| format!("invalid instruction count {:?}", count)),
+        ("send", [Token::Text(text)]) => Ok(Parsed::Command(Command::Send(text.clone()))),
+        ("label", [Token::Word(_)]) => Ok(Parsed::Command(Command::Label)),
+        ("goto", [Token::Word(label)]) => Ok(Parsed::Goto(label.clone())),
+        ("expect", _) if !arguments.is_empty() => {
+            let mut alternatives = Vec::new();
+            let mut arguments = arguments.iter().peekable();
+
+            while let Some(token) = arguments.next() {
+                let alternative = match token {
+                    Token::Text(text) => Alternative::Text(text.clone()),
+                    Token::Word(word) if word == "timeout" => Alternative::Timeout,
+                    Token::Word(word) if word == "halt" => Alternative::Halted,
+                    Token::Word(word) => return Err(format!("unexpected {:?}", word)),
+                };
+
+                let target = if arguments.peek() == Some(&&Token::Word("goto".to_string())) {
+                    arguments.next();
+                    Some(word(arguments.next()).ok_or("expected a label after goto")?)
+                } else {
+                    None
+                };
+
+                alternatives.push((alternative, target));
+            }
+
+            Ok(Parsed::Expect(alternatives))
+        }
+        ("timeout", _) => Err("expected an instruction count".to_string()),
+        ("send" | "expect", _) => Err("expected some text".to_string()),
+        ("label" | "goto", _) => Err("expected a label".to_string()),
+        (command, _) => Err(format!("unknown command {:?}", command)),
+    }
+}
+
+impl FromStr for Script {
+    type Err = ScriptError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parsed = Vec::new();
+        let mut labels = HashMap::new();
+
+        for (index, text) in s.lines().enumerate() {
+            let line = index + 1;
+            let error = |message: String| ScriptError { line, message };
+
+            let tokens = tokenise(text).map_err(error)?;
+            if tokens.is_empty() {
+                continue;
+            }
+
+            if let [Token::Word(command), Token::Word(label)] = &tokens[..] {
+                if command == "label" && labels.insert(label.clone(), parsed.len()).is_some() {
+                    return Err(error(format!("label {:?} is already defined", label)));
+                }
+            }
+
+            parsed.push((line, parse_line(&tokens).map_err(error)?));
+        }
+
+        let resolve = |line: usize, label: String| {
+            labels.get(&label).copied().ok_or(ScriptError {
+                line,
+                message: format!("unknown label {:?}", label),
+            })
+        };
+
+        let mut commands = Vec::new();
+
+        for (line, parsed) in parsed {
+            let command = match parsed {
+                Parsed::Command(command) => command,
+                Parsed::Goto(label) => Command::Goto(resolve(line, label)?),
+                Parsed::Expect(alternatives) => Command::Expect(
+                    alternatives
+                        .into_iter()
+                        .map(|(alternative, label)| {
+                            Ok((alternative, label.map(|l| resolve(line, l)).transpose()?))
+                        })
+                        .collect::<Result<Vec<_>, ScriptError>>()?,
+                ),
+            };
+
+            commands.push((line, command));
+        }
+
+        Ok(Script { commands })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Prompts with ? then echoes each line back, forever
+    const ECHO: [RegisterSize; 16] = [
+        104, 63, 3, 100, 4, 100, 1008, 100, 10, 101, 1006, 101, 2, 1105, 1, 0,
+    ];
+
+    #[test]
+    fn test_expect_and_send() {
+        let mut session = Session::new(&ECHO);
+
+        assert_eq!(session.expect("?"), Ok(String::new()));
+        assert_eq!(
+            session.expect("x"),
+            Err(ExpectError::AwaitingInput {
+                unmatched: String::new()
+            })
+        );
+
+        session.send_line("north").unwrap();
+        session.send_line("south").unwrap();
+        assert_eq!(
+            session.expect_any(&["south", "nor"]),
+            Ok(Match {
+                pattern: 1,
+                before: String::new(),
+            })
+        );
+        assert_eq!(session.expect("?"), Ok("th\n".to_string()));
+        assert_eq!(session.expect("?"), Ok("south\n".to_string()));
+    }
+
+    #[test]
+    fn test_timeouts_and_halting() {
+        let mut session = Session::new(&[1105, 1, 0]);
+        session.set_timeout(500);
+        assert_eq!(
+            session.expect("anything"),
+            Err(ExpectError::Timeout {
+                instructions: 500,
+                unmatched: String::new(),
+            })
+        );
+
+        let mut session = Session::new(&[104, 65, 104, 1000, 99]);
+        assert_eq!(
+            session.expect("B"),
+            Err(ExpectError::Halted {
+                unmatched: "A\n".to_string()
+            })
+        );
+        assert_eq!(session.take_values(), vec![1000]);
+    }
+
+    #[test]
+    fn test_scripts() {
+        let script: Script = r#"
+            # Answers the prompt, and checks the answer comes back
+            timeout 1000
+            expect "?"
+            send "hello \"there\""
+            expect "goodbye" goto wrong "hello" goto right
+            label wrong
+            expect "never"
+            label right
+            expect " \"there\"\n?" timeout goto wrong
+            send "again"
+            expect "again"
+        "#
+        .parse()
+        .unwrap();
+
+        let mut session = Session::new(&ECHO);
+        assert_eq!(script.run(&mut session), Ok(()));
+
+        let script: Script = "expect \"?\"\nexpect \"!\" halt goto end\nlabel end"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            script.run(&mut Session::new(&ECHO)),
+            Err(ScriptError {
+                line: 2,
+                message: "waiting for input with \"\" unmatched".to_string(),
+            })
+        );
+        assert_eq!(script.run(&mut Session::new(&[104, 63, 99])), Ok(()));
+    }
+
+    #[test]
+    fn test_script_errors() {
+        let error = |text: &str| Script::from_str(text).unwrap_err().to_string();
+
+        assert_eq!(error("send \"hi"), "line 1: unterminated text");
+        assert_eq!(
+            error("\n\ngoto nowhere"),
+            "line 3: unknown label \"nowhere\""
+        );
+        assert_eq!(
+            error("label a\nlabel a"),
+            "line 2: label \"a\" is already defined"
+        );
+        assert_eq!(
+            error("expect \"a\" goto"),
+            "line 1: expected a label after goto"
+        );
+        assert_eq!(
+            error("timeout soon"),
+            "line 1: invalid instruction count \"soon\""
+        );
+        assert_eq!(error("jump"), "line 1: unknown command \"jump\"");
+    }
+}
@@ -0,0 +1,579 @@
+use super::{
+    IntCodeInterpreter, IntcodeError, Memory, ParameterMode, Register, RegisterSize, StepResult,
+};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// Parameter modes only go up to the third parameter, so nothing can take more than that
+pub const MAX_PARAMETERS: usize = 3;
+
+type Execute<T> = dyn Fn(&mut Execution<T>) -> Result<StepResult<T>, IntcodeError<T>> + Send + Sync;
+
+// What an instruction runs with: its parameters, and the parts of the machine it's allowed to
+// touch. Parameters are numbered from zero
+pub struct Execution<'a, T = RegisterSize> {
+    interpreter: &'a mut IntCodeInterpreter<T>,
+    modes: [ParameterMode; MAX_PARAMETERS],
+    jumped: bool,
+}
+
+impl<'a, T: Register> Execution<'a, T> {
+    pub(crate) fn new(
+        interpreter: &'a mut IntCodeInterpreter<T>,
+        modes: [ParameterMode; MAX_PARAMETERS],
+    ) -> Self {
+        Execution {
+            interpreter,
+            modes,
+            jumped: false,
+        }
+    }
+
+    pub(crate) fn jumped(&self) -> bool {
+        self.jumped
+    }
+
+    pub fn instruction_pointer(&self) -> usize {
+        self.interpreter.instruction_pointer
+    }
+
+    pub fn mode(&self, parameter: usize) -> ParameterMode {
+        self.modes[parameter]
+    }
+
+    pub fn read(&self, parameter: usize) -> Result<T, IntcodeError<T>> {
+        self.interpreter
+            ._get_parameter_value(parameter + 1, self.modes[parameter])
+    }
+
+    pub fn write(&mut self, parameter: usize, value: T) -> Result<(), IntcodeError<T>> {
+        self.interpreter
+            ._set_memory_address(parameter + 1, value, self.modes[parameter])
+    }
+
+    // Both follow the interpreter's arithmetic policy
+    pub fn add(&self, left: T, right: T) -> Result<T, IntcodeError<T>> {
+        self.interpreter._add(left, right)
+    }
+
+    pub fn multiply(&self, left: T, right: T) -> Result<T, IntcodeError<T>> {
+        self.interpreter._multiply(left, right)
+    }
+
+    // Without a jump the instruction pointer moves on past the parameters
+    pub fn jump(&mut self, target: T) -> Result<(), IntcodeError<T>> {
+        self.interpreter.instruction_pointer = self.interpreter._to_address(target)?;
+        self.jumped = true;
+        Ok(())
+    }
+
+    // The next input without using it up, so an instruction that fails can be retried. None
+    // means there's nothing yet, and the instruction should return NeedsInput
+    pub fn peek_input(&mut self) -> Result<Option<T>, IntcodeError<T>> {
+        if self.interpreter.inputs.is_empty() {
+            match self.interpreter._read_source_input()? {
+                Some(input) => self.interpreter.inputs.push(input),
+                None => return Ok(None),
+            }
+        }
+
+        Ok(Some(self.interpreter.inputs[0].clone()))
+    }
+
+    pub fn consume_input(&mut self) {
+        if !self.interpreter.inputs.is_empty() {
+            self.interpreter.inputs.remove(0);
+        }
+    }
+
+    // Sends the value to the sink and the output queue, handing back the result to return
+    pub fn output(&mut self, value: T) -> Result<StepResult<T>, IntcodeError<T>> {
+        let interpreter = &mut *self.interpreter;
+
+        if let Some(sink) = interpreter.output_sink.as_mut() {
+            sink.write_output(value.clone())
+                .map_err(|error| IntcodeError::OutputFailed {
+                    instruction_pointer: interpreter.instruction_pointer,
+                    message: error.to_string(),
+                })?;
+        }
+
+        interpreter.outputs.push_back(value.clone());
+
+        Ok(StepResult::Output(value))
+    }
+
+    pub fn relative_offset(&self) -> T {
+        self.interpreter.relative_offset.clone()
+    }
+
+    pub fn set_relative_offset(&mut self, relative_offset: T) {
+        self.interpreter.relative_offset = relative_offset;
+    }
+
+    pub fn memory(&self) -> &Memory<T> {
+        &self.interpreter.memory
+    }
+}
+
+// One opcode's worth of behaviour. `writes` lists the parameters that are addresses to write to,
+// which tracing needs to know, and which can't be in immediate mode
+#[derive(Clone)]
+pub struct Operation<T = RegisterSize> {
+    pub name: String,
+    pub arity: usize,
+    pub writes: Vec<usize>,
+    execute: Arc<Execute<T>>,
+}
+
+impl<T: Register> Operation<T> {
+    pub fn new<F>(name: &str, arity: usize, writes: &[usize], execute: F) -> Self
+    where
+        F: Fn(&mut Execution<T>) -> Result<StepResult<T>, IntcodeError<T>> + Send + Sync + 'static,
+    {
+        assert!(
+            arity <= MAX_PARAMETERS,
+            "{} takes too many parameters",
+            name
+        );
+        assert!(
+            writes.iter().all(|parameter| *parameter < arity),
+            "{} writes to a parameter it doesn't have",
+            name
+        );
+
+        Operation {
+            name: name.to_string(),
+            arity,
+            writes: writes.to_vec(),
+            execute: Arc::new(execute),
+        }
+    }
+
+    pub fn execute(&self, execution: &mut Execution<T>) -> Result<StepResult<T>, IntcodeError<T>> {
+        (self.execute)(execution)
+    }
+}
+
+impl<T> fmt::Debug for Operation<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Operation")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .field("writes", &self.writes)
+            .finish()
+    }
+}
+
+fn compare<T: Register>(
+    execution: &mut Execution<T>,
+    test: fn(&T, &T) -> bool,
+) -> Result<StepResult<T>, IntcodeError<T>> {
+    let result = test(&execution.read(0)?, &execution.read(1)?);
+    execution.write(2, T::from_i64(result as RegisterSize))?;
+    Ok(StepResult::Continue)
+}
+
+fn jump_if<T: Register>(
+    execution: &mut Execution<T>,
+    non_zero: bool,
+) -> Result<StepResult<T>, IntcodeError<T>> {
+    if execution.read(0)?.is_zero() != non_zero {
+        let target = execution.read(1)?;
+        execution.jump(target)?;
+    }
+
+    Ok(StepResult::Continue)
+}
+
+// Opcodes are the last two digits of an instruction, so there are at most a hundred of them
+const OPCODES: usize = 100;
+
+const ALL_MODES: [ParameterMode; 3] = [
+    ParameterMode::PositionMode,
+    ParameterMode::ImmediateMode,
+    ParameterMode::RelativeMode,
+];
+
+// The machine as it was on the days the puzzles built it up
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum IsaLevel {
+    // Add, multiply and halt, in position mode only
+    Day2,
+    // Input, output, jumps and comparisons, plus immediate mode
+    Day5,
+    // The relative base and relative mode
+    #[default]
+    Day9,
+}
+
+impl IsaLevel {
+    fn opcodes(self) -> &'static [usize] {
+        match self {
+            IsaLevel::Day2 => &[1, 2, 99],
+            IsaLevel::Day5 => &[1, 2, 3, 4, 5, 6, 7, 8, 99],
+            IsaLevel::Day9 => &[1, 2, 3, 4, 5, 6, 7, 8, 9, 99],
+        }
+    }
+
+    fn modes(self) -> &'static [ParameterMode] {
+        match self {
+            IsaLevel::Day2 => &ALL_MODES[..1],
+            IsaLevel::Day5 => &ALL_MODES[..2],
+            IsaLevel::Day9 => &ALL_MODES,
+        }
+    }
+}
+
+impl fmt::Display for IsaLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IsaLevel::Day2 => write!(f, "day2"),
+            IsaLevel::Day5 => write!(f, "day5"),
+            IsaLevel::Day9 => write!(f, "day9"),
+        }
+    }
+}
+
+impl FromStr for IsaLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day2" => Ok(IsaLevel::Day2),
+            "day5" => Ok(IsaLevel::Day5),
+            "day9" => Ok(IsaLevel::Day9),
+            _ => Err(format!("unknown instruction set level {:?}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct InstructionSet<T = RegisterSize> {
+    operations: Vec<Option<Operation<T>>>,
+    modes: Vec<ParameterMode>,
+    // Which level the set still matches, forgotten as soon as it's changed
+    level: Option<IsaLevel>,
+}
+
+impl<T: Register> InstructionSet<T> {
+    // No opcodes, but every parameter mode
+    pub fn empty() -> Self {
+        InstructionSet {
+            operations: vec![None; OPCODES],
+            modes: ALL_MODES.to_vec(),
+            level: None,
+        }
+    }
+
+    // Only what the machine had at that level, so anything newer is rejected as unknown
+    pub fn for_level(level: IsaLevel) -> Self {
+        let standard = InstructionSet::standard();
+        let mut set = InstructionSet::empty();
+
+        for opcode in level.opcodes() {
+            if let Some(operation) = standard.operations[*opcode].clone() {
+                set.register(*opcode, operation);
+            }
+        }
+
+        set.set_modes(level.modes());
+        set.level = Some(level);
+        set
+    }
+
+    // Everything the 2019 puzzles use
+    pub fn standard() -> Self {
+        let mut set = InstructionSet::empty();
+
+        set.register(
+            1,
+            Operation::new("ADD", 3, &[2], |execution| {
+                let sum = execution.add(execution.read(0)?, execution.read(1)?)?;
+                execution.write(2, sum)?;
+                Ok(StepResult::Continue)
+            }),
+        );
+        set.register(
+            2,
+            Operation::new("MUL", 3, &[2], |execution| {
+                let product = execution.multiply(execution.read(0)?, execution.read(1)?)?;
+                execution.write(2, product)?;
+                Ok(StepResult::Continue)
+            }),
+        );
+        set.register(
+            3,
+            Operation::new("IN", 1, &[0], |execution| match execution.peek_input()? {
+                Some(input) => {
+                    // Only consume the input once we know the write succeeded
+                    execution.write(0, input)?;
+                    execution.consume_input();
+                    Ok(StepResult::Continue)
+                }
+                None => Ok(StepResult::NeedsInput),
+            }),
+        );
+        set.register(
+            4,
+            Operation::new("OUT", 1, &[], |execution| {
+                let value = execution.read(0)?;
+                execution.output(value)
+            }),
+        );
+        set.register(
+            5,
+            Operation::new("JNZ", 2, &[], |execution| jump_if(execution, true)),
+        );
+        set.register(
+            6,
+            Operation::new("JZ", 2, &[], |execution| jump_if(execution, false)),
+        );
+        set.register(
+            7,
+            Operation::new("LT", 3, &[2], |execution| compare(execution, |a, b| a < b)),
+        );
+        set.register(
+            8,
+            Operation::new("EQ", 3, &[2], |execution| compare(execution, |a, b| a == b)),
+        );
+        set.register(
+            9,
+            Operation::new("ARB", 1, &[], |execution| {
+                let offset = execution.add(execution.relative_offset(), execution.read(0)?)?;
+                execution.set_relative_offset(offset);
+                Ok(StepResult::Continue)
+            }),
+        );
+        set.register(
+            99,
+            Operation::new("HLT", 0, &[], |_| Ok(StepResult::Halted)),
+        );
+
+        set.level = Some(IsaLevel::Day9);
+        set
+    }
+
+    // Adds a new opcode or replaces an existing one, handing back whatever was there before
+    pub fn register(&mut self, opcode: usize, operation: Operation<T>) -> Option<Operation<T>> {
+        assert!(
+            opcode < OPCODES,
+            "opcode {} has more than two digits",
+            opcode
+        );
+        self.level = None;
+        self.operations[opcode].replace(operation)
+    }
+
+    pub fn remove(&mut self, opcode: usize) -> Option<Operation<T>> {
+        self.level = None;
+        self.operations.get_mut(opcode)?.take()
+    }
+
+    // Takes the whole instruction, ignoring its parameter modes
+    pub fn get(&self, opcode: RegisterSize) -> Option<&Operation<T>> {
+        if opcode < 0 {
+            return None;
+        }
+
+        self.operations[(opcode % OPCODES as RegisterSize) as usize].as_ref()
+    }
+
+    pub fn set_modes(&mut self, modes: &[ParameterMode]) {
+        self.level = None;
+        self.modes = modes.to_vec();
+    }
+
+    // None for custom sets, which can't be rebuilt from a name
+    pub fn level(&self) -> Option<IsaLevel> {
+        self.level
+    }
+
+    pub fn allows_mode(&self, mode: ParameterMode) -> bool {
+        self.modes.contains(&mode)
+    }
+
+    // Looks an opcode up by its mnemonic, ignoring case
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.operations.iter().position(|operation| {
+            operation
+                .as_ref()
+                .is_some_and(|operation| operation.name.eq_ignore_ascii_case(name))
+        })
+    }
+
+    pub fn opcodes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.operations
+            .iter()
+            .enumerate()
+            .filter(|(_, operation)| operation.is_some())
+            .map(|(opcode, _)| opcode)
+    }
+}
+
+impl<T: Register> Default for InstructionSet<T> {
+    fn default() -> Self {
+        InstructionSet::standard()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::{QueueOutput, TraceRecorder};
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_standard_set() {
+        let set: InstructionSet = InstructionSet::standard();
+
+        assert_eq!(
+            set.opcodes().collect::<Vec<usize>>(),
+            vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 99]
+        );
+        assert_eq!(set.get(1102).map(|operation| operation.arity), Some(3));
+        assert_eq!(set.get(3).unwrap().writes, vec![0]);
+        assert!(set.get(42).is_none());
+        assert!(set.get(-1).is_none());
+        assert_eq!(set.level(), Some(IsaLevel::Day9));
+        assert_eq!(set.find("jz"), Some(6));
+        assert_eq!(set.find("NOP"), None);
+    }
+
+    #[test]
+    fn test_isa_levels() {
+        let run = |level: IsaLevel, program: &[RegisterSize]| {
+            let mut interpreter = IntCodeInterpreter::new();
+            interpreter.set_instruction_set(InstructionSet::for_level(level));
+            interpreter.reset(program);
+            interpreter.add_input(8);
+            interpreter.run().map(|_| interpreter.drain_outputs())
+        };
+
+        // Day 2 style programs run everywhere
+        let day2 = [1, 0, 0, 0, 99];
+        for level in &[IsaLevel::Day2, IsaLevel::Day5, IsaLevel::Day9] {
+            assert_eq!(run(*level, &day2), Ok(vec![]));
+        }
+
+        assert_eq!(
+            run(IsaLevel::Day2, &[3, 0, 4, 0, 99]),
+            Err(IntcodeError::UnknownOpcode {
+                instruction_pointer: 0,
+                opcode: 3,
+            })
+        );
+        assert_eq!(
+            run(IsaLevel::Day2, &[1101, 1, 2, 0, 99]),
+            Err(IntcodeError::UnknownParameterMode {
+                instruction_pointer: 0,
+                opcode: 1101,
+                mode: 1,
+            })
+        );
+
+        // Equal to 8, from the day 5 examples
+        let day5 = [3, 3, 1108, -1, 8, 3, 4, 3, 99];
+        assert_eq!(run(IsaLevel::Day5, &day5), Ok(vec![1]));
+
+        let day9 = [109, 1, 204, -1, 99];
+        assert_eq!(
+            run(IsaLevel::Day5, &day9),
+            Err(IntcodeError::UnknownOpcode {
+                instruction_pointer: 0,
+                opcode: 109,
+            })
+        );
+        assert_eq!(
+            run(IsaLevel::Day5, &[204, 0, 99]),
+            Err(IntcodeError::UnknownParameterMode {
+                instruction_pointer: 0,
+                opcode: 204,
+                mode: 2,
+            })
+        );
+        assert_eq!(run(IsaLevel::Day9, &day9), Ok(vec![109]));
+
+        assert_eq!("day5".parse(), Ok(IsaLevel::Day5));
+        assert_eq!(IsaLevel::Day2.to_string(), "day2");
+        assert!("day11".parse::<IsaLevel>().is_err());
+    }
+
+    #[test]
+    fn test_custom_opcodes() {
+        let printed = Arc::new(Mutex::new(Vec::new()));
+        let log = printed.clone();
+
+        let mut set: InstructionSet = InstructionSet::standard();
+
+        // Debug print, which records its parameter without it counting as output
+        set.register(
+            50,
+            Operation::new("DBG", 1, &[], move |execution| {
+                log.lock().unwrap().push(execution.read(0)?);
+                Ok(StepResult::Continue)
+            }),
+        );
+
+        // Doubles a cell in place, reading and writing the same parameter
+        set.register(
+            51,
+            Operation::new("DBL", 1, &[0], |execution: &mut Execution| {
+                let value = execution.read(0)?;
+                let doubled = execution.add(value, value)?;
+                execution.write(0, doubled)?;
+                Ok(StepResult::Continue)
+            }),
+        );
+
+        // Output now sends everything twice
+        let previous = set.register(
+            4,
+            Operation::new("OUT2", 1, &[], |execution: &mut Execution| {
+                let value = execution.read(0)?;
+                execution.output(value)?;
+                execution.output(value)
+            }),
+        );
+        assert_eq!(previous.unwrap().name, "OUT");
+
+        let sink = QueueOutput::new();
+        let recorder = TraceRecorder::new();
+
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.set_instruction_set(set);
+        interpreter.set_output_sink(sink.clone());
+        interpreter.set_tracer(recorder.clone());
+        interpreter.reset(&[51, 7, 50, 7, 4, 7, 99, 21]);
+        interpreter.run().unwrap();
+
+        assert_eq!(*printed.lock().unwrap(), vec![42]);
+        assert_eq!(interpreter.drain_outputs(), vec![42, 42]);
+        assert_eq!(sink.drain(), vec![42, 42]);
+        assert_eq!(recorder.events()[0].writes, vec![(7, 42)]);
+        assert_eq!(recorder.events()[1].operands, vec![42]);
+    }
+
+    #[test]
+    fn test_removed_opcodes_are_unknown() {
+        let mut set: InstructionSet = InstructionSet::standard();
+        assert_eq!(
+            set.remove(2).map(|operation| operation.name),
+            Some("MUL".to_string())
+        );
+        assert_eq!(set.level(), None);
+
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.set_instruction_set(set);
+        interpreter.reset(&[1002, 4, 3, 4, 33]);
+
+        assert_eq!(
+            interpreter.run(),
+            Err(IntcodeError::UnknownOpcode {
+                instruction_pointer: 0,
+                opcode: 1002,
+            })
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use super::{Register, RegisterSize};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+pub trait InputSource<T = RegisterSize> {
+    // Ok(None) means nothing is available yet, so the machine should wait for more input
+    fn read_input(&mut self) -> io::Result<Option<T>>;
+}
+
+pub trait OutputSink<T = RegisterSize> {
+    fn write_output(&mut self, value: T) -> io::Result<()>;
+}
+
+fn parse_value<T: Register>(token: &str) -> io::Result<T> {
+    T::from_str(token).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid input {:?}", token),
+        )
+    })
+}
+
+fn split_values(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|token| !token.is_empty())
+}
+
+#[derive(Default)]
+pub struct QueueInput<T = RegisterSize> {
+    queue: VecDeque<T>,
+}
+
+impl QueueInput {
+    pub fn new(values: &[RegisterSize]) -> Self {
+        QueueInput::from_values(values)
+    }
+}
+
+impl<T: Register> QueueInput<T> {
+    pub fn from_values(values: &[T]) -> Self {
+        QueueInput {
+            queue: values.iter().cloned().collect(),
+        }
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.queue.push_back(value);
+    }
+}
+
+impl<T: Register> InputSource<T> for QueueInput<T> {
+    fn read_input(&mut self) -> io::Result<Option<T>> {
+        Ok(self.queue.pop_front())
+    }
+}
+
+// Shares its queue, so the embedding application can keep reading while the interpreter writes
+#[derive(Clone, Default)]
+pub struct QueueOutput<T = RegisterSize> {
+    queue: Arc<Mutex<VecDeque<T>>>,
+}
+
+impl QueueOutput {
+    pub fn new() -> Self {
+        QueueOutput::default()
+    }
+}
+
+impl<T: Register> QueueOutput<T> {
+    pub fn pop(&self) -> Option<T> {
+        self.queue.lock().unwrap().pop_front()
+    }
+
+    pub fn drain(&self) -> Vec<T> {
+        self.queue.lock().unwrap().drain(..).collect()
+    }
+}
+
+impl<T: Register> OutputSink<T> for QueueOutput<T> {
+    fn write_output(&mut self, value: T) -> io::Result<()> {
+        self.queue.lock().unwrap().push_back(value);
+        Ok(())
+    }
+}
+
+// Accepts any whitespace or comma separated values, one line at a time, at any register width
+#[derive(Default)]
+pub struct StdinInput {
+    pending: VecDeque<String>,
+}
+
+impl StdinInput {
+    pub fn new() -> Self {
+        StdinInput {
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<T: Register> InputSource<T> for StdinInput {
+    fn read_input(&mut self) -> io::Result<Option<T>> {
+        while self.pending.is_empty() {
+            let mut line = String::new();
+
+            if io::stdin().lock().read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+
+            self.pending.extend(split_values(&line).map(String::from));
+        }
+
+        parse_value(&self.pending.pop_front().unwrap()).map(Some)
+    }
+}
+
+#[derive(Default)]
+pub struct StdoutOutput;
+
+impl<T: Register> OutputSink<T> for StdoutOutput {
+    fn write_output(&mut self, value: T) -> io::Result<()> {
+        writeln!(io::stdout(), "{}", value)
+    }
+}
+
+// The register width comes from the function, so these work at any width without saying so
+pub struct FnInput<F> {
+    function: F,
+}
+
+impl<F> FnInput<F> {
+    pub fn new(function: F) -> Self {
+        FnInput { function }
+    }
+}
+
+impl<T: Register, F: FnMut() -> Option<T>> InputSource<T> for FnInput<F> {
+    fn read_input(&mut self) -> io::Result<Option<T>> {
+        Ok((self.function)())
+    }
+}
+
+pub struct FnOutput<F> {
+    function: F,
+}
+
+impl<F> FnOutput<F> {
+    pub fn new(function: F) -> Self {
+        FnOutput { function }
+    }
+}
+
+impl<T: Register, F: FnMut(T)> OutputSink<T> for FnOutput<F> {
+    fn write_output(&mut self, value: T) -> io::Result<()> {
+        (self.function)(value);
+        Ok(())
+    }
+}
+
+pub struct FileInput {
+    values: VecDeque<String>,
+}
+
+impl FileInput {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+
+        Ok(FileInput {
+            values: split_values(&contents).map(String::from).collect(),
+        })
+    }
+}
+
+impl<T: Register> InputSource<T> for FileInput {
+    fn read_input(&mut self) -> io::Result<Option<T>> {
+        match self.values.pop_front() {
+            Some(token) => parse_value(&token).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+// Writes one value per line, unbuffered so nothing is lost if the machine errors out
+pub struct FileOutput {
+    file: File,
+}
+
+impl FileOutput {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(FileOutput {
+            file: File::create(path)?,
+        })
+    }
+}
+
+impl<T: Register> OutputSink<T> for FileOutput {
+    fn write_output(&mut self, value: T) -> io::Result<()> {
+        writeln!(self.file, "{}", value)
+    }
+}
+
+// Blocks until a value arrives, and only runs dry once every sender has gone away
+pub struct ChannelInput<T = RegisterSize> {
+    receiver: Receiver<T>,
+}
+
+impl<T: Register> ChannelInput<T> {
+    pub fn new(receiver: Receiver<T>) -> Self {
+        ChannelInput { receiver }
+    }
+}
+
+impl<T: Register> InputSource<T> for ChannelInput<T> {
+    fn read_input(&mut self) -> io::Result<Option<T>> {
+        Ok(self.receiver.recv().ok())
+    }
+}
+
+pub struct ChannelOutput<T = RegisterSize> {
+    sender: Sender<T>,
+}
+
+impl<T: Register> ChannelOutput<T> {
+    pub fn new(sender: Sender<T>) -> Self {
+        ChannelOutput { sender }
+    }
+}
+
+impl<T: Register> OutputSink<T> for ChannelOutput<T> {
+    fn write_output(&mut self, value: T) -> io::Result<()> {
+        self.sender
+            .send(value)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "output receiver hung up"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use num::BigInt;
+    use std::env;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_queue_input() {
+        let mut input = QueueInput::new(&[1, 2]);
+        input.push(3);
+
+        assert_eq!(input.read_input().unwrap(), Some(1));
+        assert_eq!(input.read_input().unwrap(), Some(2));
+        assert_eq!(input.read_input().unwrap(), Some(3));
+        assert_eq!(input.read_input().unwrap(), None);
+    }
+
+    #[test]
+    fn test_queue_output_is_shared() {
+        let output = QueueOutput::new();
+        let mut sink = output.clone();
+
+        sink.write_output(4).unwrap();
+        sink.write_output(-5).unwrap();
+
+        assert_eq!(output.pop(), Some(4));
+        assert_eq!(output.drain(), vec![-5]);
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let path = env::temp_dir().join(format!("intcode_io_test_{}.txt", std::process::id()));
+
+        let mut sink: Box<dyn OutputSink> = Box::new(FileOutput::create(&path).unwrap());
+        sink.write_output(12).unwrap();
+        sink.write_output(-7).unwrap();
+
+        let mut source: Box<dyn InputSource> = Box::new(FileInput::open(&path).unwrap());
+        assert_eq!(source.read_input().unwrap(), Some(12));
+        assert_eq!(source.read_input().unwrap(), Some(-7));
+        assert_eq!(source.read_input().unwrap(), None);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_file_input_rejects_bad_values() {
+        let path = env::temp_dir().join(format!("intcode_io_bad_{}.txt", std::process::id()));
+        fs::write(&path, "1, two").unwrap();
+
+        let mut source: Box<dyn InputSource> = Box::new(FileInput::open(&path).unwrap());
+        assert_eq!(source.read_input().unwrap(), Some(1));
+        assert_eq!(
+            source.read_input().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_channels() {
+        let (input_sender, input_receiver) = channel();
+        let (output_sender, output_receiver) = channel();
+
+        let mut source: ChannelInput = ChannelInput::new(input_receiver);
+        let mut sink: ChannelOutput = ChannelOutput::new(output_sender);
+
+        input_sender.send(9).unwrap();
+        drop(input_sender);
+        assert_eq!(source.read_input().unwrap(), Some(9));
+        assert_eq!(source.read_input().unwrap(), None);
+
+        sink.write_output(10).unwrap();
+        assert_eq!(output_receiver.recv().unwrap(), 10);
+
+        drop(output_receiver);
+        assert!(sink.write_output(11).is_err());
+    }
+
+    #[test]
+    fn test_wide_values() {
+        let wide = BigInt::from(1) << 100;
+
+        let mut input = QueueInput::from_values(&[wide.clone(), -wide.clone()]);
+        assert_eq!(input.read_input().unwrap(), Some(wide.clone()));
+        assert_eq!(input.read_input().unwrap(), Some(-wide.clone()));
+
+        let output = QueueOutput::<BigInt>::default();
+        output.clone().write_output(wide.clone()).unwrap();
+        assert_eq!(output.drain(), vec![wide.clone()]);
+
+        let path = env::temp_dir().join(format!("intcode_io_wide_{}.txt", std::process::id()));
+        FileOutput::create(&path)
+            .unwrap()
+            .write_output(wide.clone())
+            .unwrap();
+
+        let mut source = FileInput::open(&path).unwrap();
+        assert_eq!(
+            InputSource::<BigInt>::read_input(&mut source).unwrap(),
+            Some(wide)
+        );
+
+        // The same file is too wide to read back as i64
+        let mut source = FileInput::open(&path).unwrap();
+        assert!(InputSource::<RegisterSize>::read_input(&mut source).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
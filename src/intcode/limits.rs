@@ -0,0 +1,76 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// Checked by run() and run_until(). Instruction, time and output limits count from the start of
+// each call, while the memory limit is on the high water mark however it got there
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Limits {
+    pub max_instructions: Option<u64>,
+    pub max_duration: Option<Duration>,
+    pub max_memory: Option<usize>,
+    pub max_outputs: Option<usize>,
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Limits::default()
+    }
+
+    pub fn instructions(mut self, max_instructions: u64) -> Self {
+        self.max_instructions = Some(max_instructions);
+        self
+    }
+
+    pub fn duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    pub fn memory(mut self, max_memory: usize) -> Self {
+        self.max_memory = Some(max_memory);
+        self
+    }
+
+    pub fn outputs(mut self, max_outputs: usize) -> Self {
+        self.max_outputs = Some(max_outputs);
+        self
+    }
+}
+
+// Which limit tripped, along with the value it was set to
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Limit {
+    Instructions(u64),
+    Duration(Duration),
+    Memory(usize),
+    Outputs(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Instructions(max) => write!(f, "instruction limit of {} reached", max),
+            Limit::Duration(max) => write!(f, "time limit of {:?} reached", max),
+            Limit::Memory(max) => write!(f, "memory limit of {} cells exceeded", max),
+            Limit::Outputs(max) => write!(f, "output limit of {} reached", max),
+        }
+    }
+}
+
+// How far a run has got towards its limits. run_until starts afresh on every call, but the
+// threaded runner keeps one going for the whole thread while it stops to clear out each output
+pub(crate) struct Progress {
+    pub(crate) started: Instant,
+    pub(crate) instructions: u64,
+    pub(crate) outputs: usize,
+}
+
+impl Progress {
+    pub(crate) fn new() -> Self {
+        Progress {
+            started: Instant::now(),
+            instructions: 0,
+            outputs: 0,
+        }
+    }
+}
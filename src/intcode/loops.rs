@@ -0,0 +1,113 @@
+use super::{Memory, Register};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+// Where a detected loop lives, as the range of addresses its instructions occupy, and how many
+// instructions it takes to come back round to the same state
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct LoopInfo {
+    pub start: usize,
+    pub end: usize,
+    pub period: u64,
+}
+
+struct Checkpoint<T> {
+    instruction_pointer: usize,
+    relative_offset: T,
+    inputs: Vec<T>,
+    memory: Memory<T>,
+}
+
+fn cell_hash<T: Register>(address: usize, value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (address, value).hash(&mut hasher);
+    hasher.finish()
+}
+
+// Brent's cycle detection over the whole machine state. A checkpoint is taken at ever doubling
+// intervals, and each later state is compared with it: the registers directly, and memory by a
+// hash of how it's changed since, with a full comparison only when the hashes say it's back to
+// where it was. The program is deterministic between I/O, so any repeat means it's stuck
+pub(crate) struct LoopDetector<T> {
+    checkpoint: Option<Checkpoint<T>>,
+    power: u64,
+    steps: u64,
+    // XOR of the hashes of every cell's value at the checkpoint and now, so zero when they match
+    memory_delta: u64,
+    lowest_address: usize,
+    highest_address: usize,
+}
+
+impl<T: Register> LoopDetector<T> {
+    pub(crate) fn new() -> Self {
+        LoopDetector {
+            checkpoint: None,
+            power: 1,
+            steps: 0,
+            memory_delta: 0,
+            lowest_address: usize::MAX,
+            highest_address: 0,
+        }
+    }
+
+    // Any I/O means the next state depends on the outside world, so start again
+    pub(crate) fn reset(&mut self) {
+        *self = LoopDetector::new();
+    }
+
+    pub(crate) fn record_write(&mut self, address: usize, old: &T, new: &T) {
+        if old != new {
+            self.memory_delta ^= cell_hash(address, old) ^ cell_hash(address, new);
+        }
+    }
+
+    // Called after each instruction, with where that instruction was and the state it left
+    pub(crate) fn check(
+        &mut self,
+        executed: (usize, usize),
+        instruction_pointer: usize,
+        relative_offset: &T,
+        inputs: &[T],
+        memory: &Memory<T>,
+    ) -> Option<LoopInfo> {
+        let (address, length) = executed;
+
+        self.steps += 1;
+        self.lowest_address = self.lowest_address.min(address);
+        self.highest_address = self.highest_address.max(address + length);
+
+        if let Some(checkpoint) = &self.checkpoint {
+            if checkpoint.instruction_pointer == instruction_pointer
+                && checkpoint.relative_offset == *relative_offset
+                && self.memory_delta == 0
+                && checkpoint.inputs == inputs
+                && checkpoint.memory.same_contents(memory)
+            {
+                return Some(LoopInfo {
+                    start: self.lowest_address,
+                    end: self.highest_address,
+                    period: self.steps,
+                });
+            }
+        }
+
+        if self.checkpoint.is_none() || self.steps == self.power {
+            if self.checkpoint.is_some() {
+                self.power *= 2;
+            }
+
+            self.checkpoint = Some(Checkpoint {
+                instruction_pointer,
+                relative_offset: relative_offset.clone(),
+                inputs: inputs.to_vec(),
+                memory: memory.clone(),
+            });
+            self.steps = 0;
+            self.memory_delta = 0;
+            self.lowest_address = usize::MAX;
+            self.highest_address = 0;
+        }
+
+        None
+    }
+}
@@ -0,0 +1,248 @@
+use super::{Register, RegisterSize};
+use std::collections::HashMap;
+use std::ops::Index;
+use std::sync::Arc;
+
+pub(crate) const PAGE_SIZE: usize = 1024;
+
+type Page<T> = Arc<[T]>;
+
+// Pages below this are kept in a flat table, since looking them up is most of the cost of running
+const NEAR_PAGES: usize = 1024;
+
+fn new_page<T: Register>() -> Page<T> {
+    vec![T::default(); PAGE_SIZE].into()
+}
+
+// Pages are reference counted so cloning memory is cheap, and only copied when one side writes
+#[derive(Clone, Default)]
+pub struct Memory<T = RegisterSize> {
+    near_pages: Vec<Option<Page<T>>>,
+    far_pages: HashMap<usize, Page<T>>,
+    high_water_mark: usize,
+    // Unallocated pages read as zero, so indexing needs something to point at
+    zero: T,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Memory::default()
+    }
+}
+
+impl<T: Register> Memory<T> {
+    pub fn from_program(program: &[T]) -> Self {
+        let mut memory = Memory::default();
+
+        for (address, value) in program.iter().enumerate() {
+            memory.write(address, value.clone());
+        }
+
+        // Trailing zeroes in the program still count as loaded memory
+        memory.high_water_mark = program.len();
+
+        memory
+    }
+
+    fn page(&self, page_number: usize) -> Option<&Page<T>> {
+        if page_number < NEAR_PAGES {
+            self.near_pages.get(page_number)?.as_ref()
+        } else {
+            self.far_pages.get(&page_number)
+        }
+    }
+
+    fn all_pages(&self) -> impl Iterator<Item = (usize, &Page<T>)> {
+        self.near_pages
+            .iter()
+            .enumerate()
+            .filter_map(|(page_number, page)| page.as_ref().map(|page| (page_number, page)))
+            .chain(
+                self.far_pages
+                    .iter()
+                    .map(|(page_number, page)| (*page_number, page)),
+            )
+    }
+
+    pub fn read(&self, address: usize) -> T {
+        self[address].clone()
+    }
+
+    pub fn write(&mut self, address: usize, value: T) {
+        // The very last address can't have one past it, so that just pins the mark at the top
+        self.high_water_mark = self.high_water_mark.max(address.saturating_add(1));
+
+        let page_number = address / PAGE_SIZE;
+
+        // No need to allocate a page just to store the value it would read as anyway
+        if value.is_zero() && self.page(page_number).is_none() {
+            return;
+        }
+
+        let page = if page_number < NEAR_PAGES {
+            if self.near_pages.len() <= page_number {
+                self.near_pages.resize(page_number + 1, None);
+            }
+
+            self.near_pages[page_number].get_or_insert_with(new_page)
+        } else {
+            self.far_pages.entry(page_number).or_insert_with(new_page)
+        };
+
+        // Skip the copy if a shared page already holds this value
+        if page[address % PAGE_SIZE] != value {
+            Arc::make_mut(page)[address % PAGE_SIZE] = value;
+        }
+    }
+
+    // One past the highest address that has been loaded or written
+    pub fn high_water_mark(&self) -> usize {
+        self.high_water_mark
+    }
+
+    pub fn len(&self) -> usize {
+        self.high_water_mark
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.high_water_mark == 0
+    }
+
+    pub fn allocated_pages(&self) -> usize {
+        self.all_pages().count()
+    }
+
+    // Pages still shared with a clone, which haven't cost any extra memory
+    pub fn shared_pages(&self) -> usize {
+        self.all_pages()
+            .filter(|(_, page)| Arc::strong_count(page) > 1)
+            .count()
+    }
+
+    // Allocated pages in address order, for anything that needs to save memory compactly
+    pub(crate) fn pages(&self) -> Vec<(usize, &[T])> {
+        let mut pages = self
+            .all_pages()
+            .map(|(page_number, page)| (page_number, &page[..]))
+            .collect::<Vec<(usize, &[T])>>();
+        pages.sort_by_key(|(page_number, _)| *page_number);
+
+        pages
+    }
+
+    // None if a page would run past the end of the address space
+    pub(crate) fn from_pages(high_water_mark: usize, pages: &[(usize, Vec<T>)]) -> Option<Self> {
+        let mut memory = Memory::default();
+
+        for (page_number, values) in pages {
+            let start = page_number.checked_mul(PAGE_SIZE)?;
+
+            for (offset, value) in values.iter().enumerate() {
+                memory.write(start.checked_add(offset)?, value.clone());
+            }
+        }
+
+        memory.high_water_mark = high_water_mark;
+
+        Some(memory)
+    }
+
+    // Cell for cell, ignoring the high water mark. Pages still shared with a clone are skipped
+    // without looking inside, which makes this cheap against a recent clone
+    pub(crate) fn same_contents(&self, other: &Memory<T>) -> bool {
+        let matches =
+            |page_number: usize, page: &Page<T>, other: &Memory<T>| match other.page(page_number) {
+                Some(other_page) => Arc::ptr_eq(page, other_page) || page == other_page,
+                None => page.iter().all(Register::is_zero),
+            };
+
+        self.all_pages()
+            .all(|(page_number, page)| matches(page_number, page, other))
+            && other
+                .all_pages()
+                .all(|(page_number, page)| matches(page_number, page, self))
+    }
+
+    pub fn to_vec(&self) -> Vec<T> {
+        (0..self.high_water_mark)
+            .map(|address| self.read(address))
+            .collect()
+    }
+}
+
+impl<T: Register> Index<usize> for Memory<T> {
+    type Output = T;
+
+    fn index(&self, address: usize) -> &T {
+        match self.page(address / PAGE_SIZE) {
+            Some(page) => &page[address % PAGE_SIZE],
+            None => &self.zero,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_unwritten_memory_reads_zero() {
+        let memory: Memory = Memory::from_program(&[1, 2, 3]);
+        assert_eq!(memory.read(2), 3);
+        assert_eq!(memory.read(3), 0);
+        assert_eq!(memory[1_000_000], 0);
+        assert_eq!(memory.high_water_mark(), 3);
+    }
+
+    #[test]
+    fn test_sparse_writes() {
+        let mut memory = Memory::new();
+        memory.write(5, 7);
+        memory.write(1 << 40, 9);
+
+        assert_eq!(memory[5], 7);
+        assert_eq!(memory.read(1 << 40), 9);
+        assert_eq!(memory.allocated_pages(), 2);
+        assert_eq!(memory.high_water_mark(), (1 << 40) + 1);
+
+        memory.write(usize::MAX, 3);
+        assert_eq!(memory[usize::MAX], 3);
+        assert_eq!(memory.high_water_mark(), usize::MAX);
+    }
+
+    #[test]
+    fn test_zero_writes_do_not_allocate() {
+        let mut memory = Memory::new();
+        memory.write(5000, 0);
+
+        assert_eq!(memory.allocated_pages(), 0);
+        assert_eq!(memory.high_water_mark(), 5001);
+    }
+
+    #[test]
+    fn test_clones_copy_pages_on_write() {
+        let mut memory = Memory::new();
+        memory.write(5, 7);
+        memory.write(5000, 9);
+
+        let mut clone = memory.clone();
+        assert_eq!(clone.shared_pages(), 2);
+
+        clone.write(5, 8);
+        clone.write(5000, 9);
+        assert_eq!(clone.shared_pages(), 1);
+        assert_eq!(memory.shared_pages(), 1);
+        assert_eq!(memory[5], 7);
+        assert_eq!(clone[5], 8);
+
+        drop(clone);
+        assert_eq!(memory.shared_pages(), 0);
+    }
+
+    #[test]
+    fn test_to_vec() {
+        let mut memory: Memory = Memory::from_program(&[1, 0, 0]);
+        memory.write(4, 5);
+        assert_eq!(memory.to_vec(), vec![1, 0, 0, 0, 5]);
+    }
+}
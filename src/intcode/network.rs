@@ -0,0 +1,518 @@
+use super::{IntCodeInterpreter, IntcodeError, RegisterSize, StepResult};
+use std::error::Error;
+use std::fmt;
+
+pub type MachineId = usize;
+
+// How many instructions a machine gets each turn before the scheduler moves on
+const DEFAULT_TIME_SLICE: usize = 1000;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Route {
+    // Outputs stay in the machine's own output queue
+    Unconnected,
+    Machine(MachineId),
+    Broadcast(Vec<MachineId>),
+    // Outputs are grouped into packets of this many values, the first being the address of the
+    // machine to deliver the rest to
+    Packets(usize),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Packet {
+    pub source: MachineId,
+    pub address: RegisterSize,
+    pub values: Vec<RegisterSize>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum NetworkState {
+    Halted,
+    // Every running machine is polling for input with nothing waiting for it
+    Idle,
+    // Every running machine is blocked on input, and there's no idle input to wake them
+    Deadlock(Vec<MachineId>),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct NetworkError {
+    pub machine: MachineId,
+    pub error: IntcodeError,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "machine {}: {}", self.machine, self.error)
+    }
+}
+
+impl Error for NetworkError {}
+
+// Routes and messages can only name machines that have been added
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct UnknownMachine(pub MachineId);
+
+impl fmt::Display for UnknownMachine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "there's no machine {}", self.0)
+    }
+}
+
+impl Error for UnknownMachine {}
+
+struct Node {
+    interpreter: IntCodeInterpreter,
+    route: Route,
+    halted: bool,
+    last_output: Option<RegisterSize>,
+}
+
+struct Turn {
+    executed: usize,
+    polled_idle: bool,
+    outputs: usize,
+}
+
+pub struct Network {
+    nodes: Vec<Node>,
+    idle_input: Option<RegisterSize>,
+    time_slice: usize,
+    unrouted: Vec<Packet>,
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Network {
+    pub fn new() -> Self {
+        Network {
+            nodes: Vec::new(),
+            idle_input: None,
+            time_slice: DEFAULT_TIME_SLICE,
+            unrouted: Vec::new(),
+        }
+    }
+
+    pub fn add_machine(&mut self, program: &[RegisterSize]) -> MachineId {
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(program);
+
+        self.add_interpreter(interpreter)
+    }
+
+    pub fn add_interpreter(&mut self, interpreter: IntCodeInterpreter) -> MachineId {
+        self.nodes.push(Node {
+            interpreter,
+            route: Route::Unconnected,
+            halted: false,
+            last_output: None,
+        });
+
+        self.nodes.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn machine(&self, id: MachineId) -> &IntCodeInterpreter {
+        &self.nodes[id].interpreter
+    }
+
+    pub fn machine_mut(&mut self, id: MachineId) -> &mut IntCodeInterpreter {
+        &mut self.nodes[id].interpreter
+    }
+
+    fn _check_machines(&self, machines: &[MachineId]) -> Result<(), UnknownMachine> {
+        match machines.iter().find(|id| **id >= self.nodes.len()) {
+            Some(id) => Err(UnknownMachine(*id)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn set_route(&mut self, from: MachineId, route: Route) -> Result<(), UnknownMachine> {
+        self._check_machines(&[from])?;
+
+        match &route {
+            Route::Machine(to) => self._check_machines(&[*to])?,
+            Route::Broadcast(to) => self._check_machines(to)?,
+            Route::Unconnected | Route::Packets(_) => {}
+        }
+
+        self.nodes[from].route = route;
+
+        Ok(())
+    }
+
+    pub fn connect(&mut self, from: MachineId, to: MachineId) -> Result<(), UnknownMachine> {
+        self.set_route(from, Route::Machine(to))
+    }
+
+    pub fn broadcast(&mut self, from: MachineId, to: &[MachineId]) -> Result<(), UnknownMachine> {
+        self.set_route(from, Route::Broadcast(to.to_vec()))
+    }
+
+    pub fn route_packets(
+        &mut self,
+        from: MachineId,
+        packet_size: usize,
+    ) -> Result<(), UnknownMachine> {
+        self.set_route(from, Route::Packets(packet_size))
+    }
+
+    // Each machine feeds the next, and the last one's outputs stay put. Nothing is connected if
+    // any of them are unknown
+    pub fn chain(&mut self, machines: &[MachineId]) -> Result<(), UnknownMachine> {
+        self._check_machines(machines)?;
+
+        for pair in machines.windows(2) {
+            self.connect(pair[0], pair[1])?;
+        }
+
+        Ok(())
+    }
+
+    // A chain where the last machine feeds back into the first
+    pub fn ring(&mut self, machines: &[MachineId]) -> Result<(), UnknownMachine> {
+        self.chain(machines)?;
+
+        if let (Some(first), Some(last)) = (machines.first(), machines.last()) {
+            self.connect(*last, *first)?;
+        }
+
+        Ok(())
+    }
+
+    // Given to a machine that reads with nothing queued, rather than leaving it blocked
+    pub fn set_idle_input(&mut self, value: RegisterSize) {
+        self.idle_input = Some(value);
+    }
+
+    pub fn set_time_slice(&mut self, instructions: usize) {
+        self.time_slice = instructions.max(1);
+    }
+
+    pub fn send(&mut self, to: MachineId, value: RegisterSize) -> Result<(), UnknownMachine> {
+        self.send_packet(to, &[value])
+    }
+
+    pub fn send_packet(
+        &mut self,
+        to: MachineId,
+        values: &[RegisterSize],
+    ) -> Result<(), UnknownMachine> {
+        self._check_machines(&[to])?;
+        self._deliver(to, values);
+
+        Ok(())
+    }
+
+    // Only for machines already known to exist
+    fn _deliver(&mut self, to: MachineId, values: &[RegisterSize]) {
+        for value in values {
+            self.nodes[to].interpreter.add_input(*value);
+        }
+    }
+
+    // The most recent value a machine output, wherever it was routed to
+    pub fn last_output(&self, id: MachineId) -> Option<RegisterSize> {
+        self.nodes[id].last_output
+    }
+
+    pub fn halted(&self, id: MachineId) -> bool {
+        self.nodes[id].halted
+    }
+
+    // Packets addressed to machines that don't exist, oldest first
+    pub fn take_unrouted(&mut self) -> Vec<Packet> {
+        self.unrouted.drain(..).collect()
+    }
+
+    fn _take_turn(&mut self, id: MachineId) -> Result<Turn, NetworkError> {
+        let idle_input = self.idle_input;
+        let node = &mut self.nodes[id];
+        let outputs_before = node.interpreter.outputs().len();
+
+        let mut turn = Turn {
+            executed: 0,
+            polled_idle: false,
+            outputs: 0,
+        };
+
+        while turn.executed < self.time_slice {
+            let result = node
+                .interpreter
+                .step()
+                .map_err(|error| NetworkError { machine: id, error })?;
+
+            match result {
+                StepResult::NeedsInput => match idle_input {
+                    // Only once a turn, otherwise a polling machine would use up its whole slice
+                    Some(value) if !turn.polled_idle => {
+                        node.interpreter.add_input(value);
+                        turn.polled_idle = true;
+                    }
+                    _ => break,
+                },
+                StepResult::Halted => {
+                    node.halted = true;
+                    break;
+                }
+                _ => turn.executed += 1,
+            }
+        }
+
+        turn.outputs = node.interpreter.outputs().len() - outputs_before;
+
+        if turn.outputs > 0 {
+            node.last_output = node.interpreter.last_output();
+        }
+
+        Ok(turn)
+    }
+
+    fn _route_outputs(&mut self, id: MachineId) {
+        let route = self.nodes[id].route.clone();
+
+        match route {
+            Route::Unconnected => {}
+            Route::Machine(to) => {
+                let values = self.nodes[id].interpreter.drain_outputs();
+                self._deliver(to, &values);
+            }
+            Route::Broadcast(to) => {
+                let values = self.nodes[id].interpreter.drain_outputs();
+
+                for machine in &to {
+                    self._deliver(*machine, &values);
+                }
+            }
+            Route::Packets(size) => {
+                // Partial packets wait in the output queue until the rest arrives
+                while size > 0 && self.nodes[id].interpreter.outputs().len() >= size {
+                    let interpreter = &mut self.nodes[id].interpreter;
+                    let address = interpreter.take_output().unwrap();
+                    let values = (1..size)
+                        .map(|_| interpreter.take_output().unwrap())
+                        .collect::<Vec<RegisterSize>>();
+
+                    if address >= 0 && (address as usize) < self.nodes.len() {
+                        self._deliver(address as usize, &values);
+                    } else {
+                        self.unrouted.push(Packet {
+                            source: id,
+                            address,
+                            values,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    // Gives every running machine one turn, routing its outputs as soon as the turn ends
+    pub fn run_round(&mut self) -> Result<Option<NetworkState>, NetworkError> {
+        let mut executed = 0;
+        let mut outputs = 0;
+        let mut all_polled_idle = true;
+
+        for id in 0..self.nodes.len() {
+            if self.nodes[id].halted {
+                continue;
+            }
+
+            let turn = self._take_turn(id)?;
+            self._route_outputs(id);
+
+            executed += turn.executed;
+            outputs += turn.outputs;
+            all_polled_idle &= turn.polled_idle || self.nodes[id].halted;
+        }
+
+        let running = (0..self.nodes.len())
+            .filter(|id| !self.nodes[*id].halted)
+            .collect::<Vec<MachineId>>();
+
+        if running.is_empty() {
+            return Ok(Some(NetworkState::Halted));
+        }
+
+        let queues_empty = running
+            .iter()
+            .all(|id| self.nodes[*id].interpreter.pending_inputs().is_empty());
+
+        if self.idle_input.is_some() {
+            if outputs == 0 && all_polled_idle && queues_empty {
+                return Ok(Some(NetworkState::Idle));
+            }
+        } else if executed == 0 {
+            return Ok(Some(NetworkState::Deadlock(running)));
+        }
+
+        Ok(None)
+    }
+
+    // Runs until every machine halts or the network can't make progress on its own
+    pub fn run(&mut self) -> Result<NetworkState, NetworkError> {
+        loop {
+            if let Some(state) = self.run_round()? {
+                return Ok(state);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::intcode::asm::assemble;
+    use crate::intcode::test_programs::{COUNTDOWN, INCREMENT};
+    use crate::intcode::InstructionSet;
+
+    #[test]
+    fn test_chain() {
+        let mut network = Network::new();
+        let machines = (0..3)
+            .map(|_| network.add_machine(&INCREMENT))
+            .collect::<Vec<MachineId>>();
+        network.chain(&machines).unwrap();
+
+        network.send(0, 10).unwrap();
+        network.send(0, 20).unwrap();
+
+        assert_eq!(network.run(), Ok(NetworkState::Deadlock(machines)));
+        assert_eq!(network.machine(2).outputs(), &[13, 23]);
+        assert_eq!(network.last_output(0), Some(21));
+    }
+
+    #[test]
+    fn test_ring_runs_until_halt() {
+        let mut network = Network::new();
+        let a = network.add_machine(&COUNTDOWN);
+        let b = network.add_machine(&COUNTDOWN);
+        network.ring(&[a, b]).unwrap();
+        network.send(a, 5).unwrap();
+
+        assert_eq!(network.run(), Ok(NetworkState::Halted));
+        assert_eq!(network.last_output(a), Some(0));
+        assert_eq!(network.last_output(b), Some(1));
+    }
+
+    #[test]
+    fn test_broadcast() {
+        let mut network = Network::new();
+        let source = network.add_machine(&INCREMENT);
+        let listeners = [
+            network.add_machine(&INCREMENT),
+            network.add_machine(&INCREMENT),
+        ];
+        network.broadcast(source, &listeners).unwrap();
+        network.send(source, 1).unwrap();
+
+        network.run().unwrap();
+        assert_eq!(network.machine(listeners[0]).outputs(), &[3]);
+        assert_eq!(network.machine(listeners[1]).outputs(), &[3]);
+    }
+
+    #[test]
+    fn test_packets_and_idle() {
+        // Sends its first input, multiplied by two, as a packet to the address in its second,
+        // then polls for packets and passes on each value it gets to address 7
+        let source = "
+                    IN [value]
+                    IN [address]
+                    MUL [value], 2, [value]
+                    OUT [address]
+                    OUT [value]
+            poll:   IN [value]
+                    EQ [value], -1, [empty]
+                    JNZ [empty], poll
+                    OUT 7
+                    OUT [value]
+                    JNZ 1, poll
+            value:  .data 0
+            address: .data 0
+            empty:  .data 0
+        ";
+        let instructions: InstructionSet = InstructionSet::standard();
+        let program = assemble(&instructions, source).unwrap();
+
+        let mut network = Network::new();
+
+        for (id, target) in [(0, 1), (1, 0)].iter() {
+            let machine = network.add_machine(&program);
+            assert_eq!(machine, *id);
+
+            network.route_packets(machine, 2).unwrap();
+            network.send(machine, 21 + machine as RegisterSize).unwrap();
+            network.send(machine, *target).unwrap();
+        }
+
+        network.set_idle_input(-1);
+
+        assert_eq!(network.run(), Ok(NetworkState::Idle));
+        assert_eq!(
+            network.take_unrouted(),
+            vec![
+                Packet {
+                    source: 1,
+                    address: 7,
+                    values: vec![42],
+                },
+                Packet {
+                    source: 0,
+                    address: 7,
+                    values: vec![44],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_machines() {
+        let mut network = Network::new();
+        let a = network.add_machine(&INCREMENT);
+        let b = network.add_machine(&INCREMENT);
+
+        assert_eq!(network.connect(a, 2), Err(UnknownMachine(2)));
+        assert_eq!(network.broadcast(5, &[a]), Err(UnknownMachine(5)));
+        assert_eq!(network.chain(&[a, b, 3]), Err(UnknownMachine(3)));
+        assert_eq!(network.send_packet(9, &[1, 2]), Err(UnknownMachine(9)));
+        assert_eq!(
+            network.send(7, 1).unwrap_err().to_string(),
+            "there's no machine 7"
+        );
+
+        // Nothing was connected or delivered along the way
+        network.send(a, 1).unwrap();
+        assert_eq!(network.run(), Ok(NetworkState::Deadlock(vec![a, b])));
+        assert_eq!(network.machine(a).outputs(), &[2]);
+        assert!(network.machine(b).outputs().is_empty());
+    }
+
+    #[test]
+    fn test_errors_name_the_machine() {
+        let mut network = Network::new();
+        network.add_machine(&INCREMENT);
+        network.add_machine(&[42]);
+
+        assert_eq!(
+            network.run(),
+            Err(NetworkError {
+                machine: 1,
+                error: IntcodeError::UnknownOpcode {
+                    instruction_pointer: 0,
+                    opcode: 42,
+                },
+            })
+        );
+    }
+}
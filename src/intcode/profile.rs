@@ -0,0 +1,224 @@
+use super::{InstructionSet, Register, RegisterSize};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Profile {
+    pub instructions: u64,
+    pub inputs: u64,
+    pub outputs: u64,
+    // Keyed on the opcode without its parameter modes
+    pub opcodes: HashMap<RegisterSize, u64>,
+    // Executions of each instruction address, along with the opcode last seen there
+    pub addresses: HashMap<usize, (RegisterSize, u64)>,
+    // Data reads and writes through operands, not counting instruction fetches or immediates
+    pub reads: HashMap<usize, u64>,
+    pub writes: HashMap<usize, u64>,
+}
+
+fn opcode_name<T: Register>(instructions: &InstructionSet<T>, opcode: RegisterSize) -> String {
+    match instructions.get(opcode) {
+        Some(operation) => operation.name.clone(),
+        None => opcode.to_string(),
+    }
+}
+
+// Busiest first, with ties in key order so reports come out the same every time
+fn sorted_by_count<K: Copy + Ord, V: Copy>(
+    counts: impl Iterator<Item = (K, V, u64)>,
+) -> Vec<(K, V, u64)> {
+    let mut sorted = counts.collect::<Vec<(K, V, u64)>>();
+    sorted.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+    sorted
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Profile::default()
+    }
+
+    pub(crate) fn record_instruction(&mut self, address: usize, opcode: RegisterSize) {
+        self.instructions += 1;
+        *self.opcodes.entry(opcode % 100).or_insert(0) += 1;
+
+        let entry = self.addresses.entry(address).or_insert((opcode, 0));
+        *entry = (opcode, entry.1 + 1);
+    }
+
+    pub(crate) fn record_read(&mut self, address: usize) {
+        *self.reads.entry(address).or_insert(0) += 1;
+    }
+
+    pub(crate) fn record_write(&mut self, address: usize) {
+        *self.writes.entry(address).or_insert(0) += 1;
+    }
+
+    fn hot_opcodes(&self) -> Vec<(RegisterSize, (), u64)> {
+        sorted_by_count(
+            self.opcodes
+                .iter()
+                .map(|(opcode, count)| (*opcode, (), *count)),
+        )
+    }
+
+    fn hot_addresses(&self) -> Vec<(usize, RegisterSize, u64)> {
+        sorted_by_count(
+            self.addresses
+                .iter()
+                .map(|(address, (opcode, count))| (*address, *opcode, *count)),
+        )
+    }
+
+    // Reads and writes together, so the cells doing the most work of either kind come first
+    fn hot_cells(&self) -> Vec<(usize, (u64, u64), u64)> {
+        let mut cells = HashMap::new();
+
+        for (address, count) in &self.reads {
+            cells.entry(*address).or_insert((0, 0)).0 = *count;
+        }
+
+        for (address, count) in &self.writes {
+            cells.entry(*address).or_insert((0, 0)).1 = *count;
+        }
+
+        sorted_by_count(
+            cells
+                .into_iter()
+                .map(|(address, (reads, writes))| (address, (reads, writes), reads + writes)),
+        )
+    }
+
+    // Limited to the busiest `top` entries in each section, with opcodes named by the given set
+    pub fn report<T: Register>(&self, instructions: &InstructionSet<T>, top: usize) -> String {
+        let mut report = String::new();
+
+        writeln!(report, "instructions: {}", self.instructions).unwrap();
+        writeln!(report, "inputs: {}", self.inputs).unwrap();
+        writeln!(report, "outputs: {}", self.outputs).unwrap();
+
+        writeln!(report, "\nopcode        count       %").unwrap();
+
+        for (opcode, _, count) in self.hot_opcodes().iter().take(top) {
+            writeln!(
+                report,
+                "{:<6} {:>12} {:>6.2}%",
+                opcode_name(instructions, *opcode),
+                count,
+                percent(*count, self.instructions)
+            )
+            .unwrap();
+        }
+
+        writeln!(report, "\naddress       count       %  instruction").unwrap();
+
+        for (address, opcode, count) in self.hot_addresses().iter().take(top) {
+            writeln!(
+                report,
+                "{:>7} {:>12} {:>6.2}%  {}",
+                address,
+                count,
+                percent(*count, self.instructions),
+                opcode_name(instructions, *opcode)
+            )
+            .unwrap();
+        }
+
+        writeln!(report, "\ncell          reads       writes").unwrap();
+
+        for (address, (reads, writes), _) in self.hot_cells().iter().take(top) {
+            writeln!(report, "{:>7} {:>12} {:>12}", address, reads, writes).unwrap();
+        }
+
+        report
+    }
+
+    // Everything, one count per row, in the same order as the text report
+    pub fn to_csv<T: Register>(&self, instructions: &InstructionSet<T>) -> String {
+        let mut csv = "kind,key,detail,count\n".to_string();
+
+        writeln!(csv, "total,instructions,,{}", self.instructions).unwrap();
+        writeln!(csv, "total,inputs,,{}", self.inputs).unwrap();
+        writeln!(csv, "total,outputs,,{}", self.outputs).unwrap();
+
+        for (opcode, _, count) in self.hot_opcodes() {
+            let name = opcode_name(instructions, opcode);
+            writeln!(csv, "opcode,{},{},{}", opcode, name, count).unwrap();
+        }
+
+        for (address, opcode, count) in self.hot_addresses() {
+            let name = opcode_name(instructions, opcode);
+            writeln!(csv, "address,{},{},{}", address, name, count).unwrap();
+        }
+
+        for (address, (reads, writes), _) in self.hot_cells() {
+            if reads > 0 {
+                writeln!(csv, "read,{},,{}", address, reads).unwrap();
+            }
+
+            if writes > 0 {
+                writeln!(csv, "write,{},,{}", address, writes).unwrap();
+            }
+        }
+
+        csv
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::IntCodeInterpreter;
+
+    fn countdown_profile() -> Profile {
+        // Outputs 3, 2, 1 then halts
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.set_profiling(true);
+        interpreter.reset(&[4, 10, 1001, 10, -1, 10, 1005, 10, 0, 99, 3]);
+        interpreter.run().unwrap();
+
+        interpreter.take_profile().unwrap()
+    }
+
+    #[test]
+    fn test_counts() {
+        let profile = countdown_profile();
+
+        assert_eq!(profile.instructions, 10);
+        assert_eq!(profile.outputs, 3);
+        assert_eq!(profile.inputs, 0);
+        assert_eq!(profile.opcodes[&1], 3);
+        assert_eq!(profile.opcodes[&99], 1);
+        assert_eq!(profile.addresses[&6], (1005, 3));
+        // Three outputs, three adds and three jumps all read the counter
+        assert_eq!(profile.reads[&10], 9);
+        assert_eq!(profile.writes[&10], 3);
+        assert_eq!(profile.reads.len(), 1);
+    }
+
+    #[test]
+    fn test_reports() {
+        let profile = countdown_profile();
+
+        let set: InstructionSet = InstructionSet::standard();
+
+        let report = profile.report(&set, 2);
+        assert!(report.starts_with("instructions: 10\n"));
+        assert!(report.contains("\nADD               3  30.00%\n"));
+        assert!(report.contains("\n      0            3  30.00%  OUT\n"));
+        assert!(!report.contains("HLT"));
+
+        let csv = profile.to_csv(&set);
+        assert!(csv.starts_with("kind,key,detail,count\ntotal,instructions,,10\n"));
+        assert!(csv.contains("\nopcode,99,HLT,1\n"));
+        assert!(csv.contains("\naddress,6,JNZ,3\n"));
+        assert!(csv.ends_with("read,10,,9\nwrite,10,,3\n"));
+    }
+}
@@ -0,0 +1,236 @@
+use super::{Register, RegisterSize};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::path::Path;
+use std::str::FromStr;
+
+// Lines and columns count from one, with columns in characters
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ProgramError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl Error for ProgramError {}
+
+// Intcode in the usual comma separated text form. Whitespace and line breaks can go anywhere
+// between values, # starts a comment running to the end of the line, and a trailing comma is
+// fine. Comments don't survive the trip back to text
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Program<T = RegisterSize> {
+    values: Vec<T>,
+}
+
+impl<T: Register> Program<T> {
+    pub fn from_reader<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+
+        Program::from_str(&text).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Program::from_reader(File::open(path)?)
+    }
+
+    pub fn into_values(self) -> Vec<T> {
+        self.values
+    }
+}
+
+impl<T> From<Vec<T>> for Program<T> {
+    fn from(values: Vec<T>) -> Self {
+        Program { values }
+    }
+}
+
+impl<T> Deref for Program<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.values
+    }
+}
+
+// A value's text and where it started, or where its comma is if it's missing
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn parse<T: Register>(&self) -> Result<T, ProgramError> {
+        let error = |message: String| ProgramError {
+            line: self.line,
+            column: self.column,
+            message,
+        };
+
+        let text = self.text.trim();
+
+        if text.is_empty() {
+            Err(error("expected a value".to_string()))
+        } else if text.contains(char::is_whitespace) {
+            Err(error(format!("expected a comma in {:?}", text)))
+        } else {
+            T::from_str(text).map_err(|_| error(format!("invalid value {:?}", text)))
+        }
+    }
+}
+
+fn tokenise(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut current = Token {
+        text: String::new(),
+        line: 1,
+        column: 1,
+    };
+
+    for (line_index, line) in text.lines().enumerate() {
+        let code = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+
+        for (column_index, c) in code.chars().enumerate() {
+            let (line, column) = (line_index + 1, column_index + 1);
+
+            if c == ',' {
+                if current.text.trim().is_empty() {
+                    current.line = line;
+                    current.column = column;
+                }
+
+                tokens.push(current);
+                current = Token {
+                    text: String::new(),
+                    line,
+                    column,
+                };
+            } else {
+                if current.text.trim().is_empty() && !c.is_whitespace() {
+                    current.line = line;
+                    current.column = column;
+                }
+
+                current.text.push(c);
+            }
+        }
+
+        current.text.push('\n');
+    }
+
+    // Nothing after the last comma is fine, so a trailing one doesn't matter
+    if !current.text.trim().is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+impl<T: Register> FromStr for Program<T> {
+    type Err = ProgramError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = tokenise(s)
+            .iter()
+            .map(Token::parse)
+            .collect::<Result<Vec<T>, ProgramError>>()?;
+
+        Ok(Program { values })
+    }
+}
+
+// One line, comma separated, which is the same format the puzzles come in
+impl<T: fmt::Display> fmt::Display for Program<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, value) in self.values.iter().enumerate() {
+            if index > 0 {
+                write!(f, ",")?;
+            }
+
+            write!(f, "{}", value)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use num::BigInt;
+
+    #[test]
+    fn test_parsing() {
+        let program: Program = "1,0,0,0,99\n".parse().unwrap();
+        assert_eq!(&program[..], &[1, 0, 0, 0, 99]);
+
+        let program: Program = "# Adds two numbers\n1101, 2,\n  -3, 4, # then halts\n99,\n"
+            .parse()
+            .unwrap();
+        assert_eq!(program.into_values(), vec![1101, 2, -3, 4, 99]);
+
+        let program: Program<BigInt> = "104,123456789012345678901234567890,99".parse().unwrap();
+        assert_eq!(
+            program[1],
+            "123456789012345678901234567890".parse().unwrap()
+        );
+
+        assert!(Program::<RegisterSize>::from_str("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_errors_point_at_the_token() {
+        let error = |text: &str| Program::<RegisterSize>::from_str(text).unwrap_err();
+
+        assert_eq!(
+            error("1,2,\n3,x4,99"),
+            ProgramError {
+                line: 2,
+                column: 3,
+                message: "invalid value \"x4\"".to_string(),
+            }
+        );
+        assert_eq!(
+            error("1,,2").to_string(),
+            "line 1, column 3: expected a value"
+        );
+        assert_eq!(
+            error("1,2\n3,4").to_string(),
+            "line 1, column 3: expected a comma in \"2\\n3\""
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+        let program: Program = text.parse().unwrap();
+
+        assert_eq!(program.to_string(), text);
+        assert_eq!(program.to_string().parse::<Program>(), Ok(program.clone()));
+        assert_eq!(
+            Program::from_reader(text.as_bytes()).unwrap(),
+            Program::from(program.to_vec())
+        );
+
+        let error = Program::<RegisterSize>::from_reader("1,a".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "line 1, column 3: invalid value \"a\"");
+    }
+}
@@ -0,0 +1,244 @@
+use super::RegisterSize;
+use num::{BigInt, ToPrimitive};
+use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
+
+// What happens when addition or multiplication overflows the register. BigInt never overflows,
+// so all three behave the same there
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum ArithmeticPolicy {
+    Wrapping,
+    // Stops the machine with an overflow error
+    #[default]
+    Checked,
+    Saturating,
+}
+
+// What a memory cell can hold. Opcodes, parameter modes and addresses are always small, so the
+// interpreter narrows them to i64 and usize, while everything else stays at the full width
+pub trait Register:
+    Clone + Default + Ord + Hash + fmt::Debug + fmt::Display + FromStr + Send + Sync + 'static
+{
+    fn from_i64(value: RegisterSize) -> Self;
+
+    // None when the value doesn't fit
+    fn to_i64(&self) -> Option<RegisterSize>;
+    fn to_usize(&self) -> Option<usize>;
+
+    // Snapshots and traces go through BigInt for anything too wide for an i64
+    fn to_bigint(&self) -> BigInt;
+    fn from_bigint(value: &BigInt) -> Option<Self>;
+
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn wrapping_add(&self, other: &Self) -> Self;
+    fn wrapping_mul(&self, other: &Self) -> Self;
+    fn saturating_add(&self, other: &Self) -> Self;
+    fn saturating_mul(&self, other: &Self) -> Self;
+
+    // None only when the policy is checked and the result doesn't fit
+    fn add_with(&self, other: &Self, policy: ArithmeticPolicy) -> Option<Self> {
+        match policy {
+            ArithmeticPolicy::Wrapping => Some(self.wrapping_add(other)),
+            ArithmeticPolicy::Checked => self.checked_add(other),
+            ArithmeticPolicy::Saturating => Some(self.saturating_add(other)),
+        }
+    }
+
+    fn mul_with(&self, other: &Self, policy: ArithmeticPolicy) -> Option<Self> {
+        match policy {
+            ArithmeticPolicy::Wrapping => Some(self.wrapping_mul(other)),
+            ArithmeticPolicy::Checked => self.checked_mul(other),
+            ArithmeticPolicy::Saturating => Some(self.saturating_mul(other)),
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn is_negative(&self) -> bool {
+        *self < Self::default()
+    }
+}
+
+impl Register for i64 {
+    fn from_i64(value: RegisterSize) -> Self {
+        value
+    }
+
+    fn to_i64(&self) -> Option<RegisterSize> {
+        Some(*self)
+    }
+
+    fn to_usize(&self) -> Option<usize> {
+        ToPrimitive::to_usize(self)
+    }
+
+    fn to_bigint(&self) -> BigInt {
+        BigInt::from(*self)
+    }
+
+    fn from_bigint(value: &BigInt) -> Option<Self> {
+        ToPrimitive::to_i64(value)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i64::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i64::checked_mul(*self, *other)
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        i64::wrapping_add(*self, *other)
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        i64::wrapping_mul(*self, *other)
+    }
+
+    fn saturating_add(&self, other: &Self) -> Self {
+        i64::saturating_add(*self, *other)
+    }
+
+    fn saturating_mul(&self, other: &Self) -> Self {
+        i64::saturating_mul(*self, *other)
+    }
+}
+
+impl Register for i128 {
+    fn from_i64(value: RegisterSize) -> Self {
+        value as i128
+    }
+
+    fn to_i64(&self) -> Option<RegisterSize> {
+        ToPrimitive::to_i64(self)
+    }
+
+    fn to_usize(&self) -> Option<usize> {
+        ToPrimitive::to_usize(self)
+    }
+
+    fn to_bigint(&self) -> BigInt {
+        BigInt::from(*self)
+    }
+
+    fn from_bigint(value: &BigInt) -> Option<Self> {
+        ToPrimitive::to_i128(value)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i128::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i128::checked_mul(*self, *other)
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        i128::wrapping_add(*self, *other)
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        i128::wrapping_mul(*self, *other)
+    }
+
+    fn saturating_add(&self, other: &Self) -> Self {
+        i128::saturating_add(*self, *other)
+    }
+
+    fn saturating_mul(&self, other: &Self) -> Self {
+        i128::saturating_mul(*self, *other)
+    }
+}
+
+impl Register for BigInt {
+    fn from_i64(value: RegisterSize) -> Self {
+        BigInt::from(value)
+    }
+
+    fn to_i64(&self) -> Option<RegisterSize> {
+        ToPrimitive::to_i64(self)
+    }
+
+    fn to_usize(&self) -> Option<usize> {
+        ToPrimitive::to_usize(self)
+    }
+
+    fn to_bigint(&self) -> BigInt {
+        self.clone()
+    }
+
+    fn from_bigint(value: &BigInt) -> Option<Self> {
+        Some(value.clone())
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        self * other
+    }
+
+    fn saturating_add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn saturating_mul(&self, other: &Self) -> Self {
+        self * other
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_narrowing() {
+        assert_eq!(Register::to_usize(&-1i64), None);
+        assert_eq!(Register::to_i64(&(1i128 << 70)), None);
+        assert_eq!(Register::to_usize(&BigInt::from(1234)), Some(1234));
+        assert!(Register::is_negative(&BigInt::from(-5)));
+        assert!(Register::is_zero(&<BigInt as Register>::from_i64(0)));
+    }
+
+    #[test]
+    fn test_arithmetic_policies() {
+        let max = i64::MAX;
+
+        assert_eq!(
+            Register::add_with(&max, &1, ArithmeticPolicy::Checked),
+            None
+        );
+        assert_eq!(
+            Register::add_with(&max, &1, ArithmeticPolicy::Wrapping),
+            Some(i64::MIN)
+        );
+        assert_eq!(
+            Register::mul_with(&max, &-2, ArithmeticPolicy::Saturating),
+            Some(i64::MIN)
+        );
+        assert_eq!(
+            Register::mul_with(&6i128, &7, ArithmeticPolicy::Checked),
+            Some(42)
+        );
+
+        let big = BigInt::from(max);
+        assert_eq!(
+            Register::mul_with(&big, &big, ArithmeticPolicy::Checked),
+            Some(BigInt::from(max) * BigInt::from(max))
+        );
+    }
+}
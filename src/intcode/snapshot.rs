@@ -0,0 +1,325 @@
+use super::memory::PAGE_SIZE;
+use super::varint::{read_register, read_required, write_register, write_unsigned};
+use super::{ArithmeticPolicy, IsaLevel, Memory, Register, RegisterSize};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const SNAPSHOT_MAGIC: &[u8; 4] = b"ICSN";
+const SNAPSHOT_VERSION: u8 = 2;
+
+// File layout after the magic and version byte, all as LEB128 varints (zigzag for signed values):
+// arithmetic policy, instruction set level (0 for a custom set), instruction pointer, relative
+// offset, running flag, pending input count and values, buffered output count and values, memory
+// high water mark, page count, then each page's number followed by its values. Values too wide
+// for an i64 just carry on into more bytes, so the format is the same at every register width
+#[derive(Clone)]
+pub struct Snapshot<T = RegisterSize> {
+    pub arithmetic: ArithmeticPolicy,
+    // None when the machine had a custom instruction set, which has to be set up again by hand
+    pub isa_level: Option<IsaLevel>,
+    pub memory: Memory<T>,
+    pub instruction_pointer: usize,
+    pub relative_offset: T,
+    pub inputs: Vec<T>,
+    pub outputs: VecDeque<T>,
+    pub running: bool,
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn policy_number(policy: ArithmeticPolicy) -> u64 {
+    match policy {
+        ArithmeticPolicy::Wrapping => 0,
+        ArithmeticPolicy::Checked => 1,
+        ArithmeticPolicy::Saturating => 2,
+    }
+}
+
+fn policy_from_number(number: u64) -> io::Result<ArithmeticPolicy> {
+    match number {
+        0 => Ok(ArithmeticPolicy::Wrapping),
+        1 => Ok(ArithmeticPolicy::Checked),
+        2 => Ok(ArithmeticPolicy::Saturating),
+        _ => Err(invalid_data(format!(
+            "unknown arithmetic policy {}",
+            number
+        ))),
+    }
+}
+
+// Levels are stored as the day they belong to
+fn level_number(level: Option<IsaLevel>) -> u64 {
+    match level {
+        None => 0,
+        Some(IsaLevel::Day2) => 2,
+        Some(IsaLevel::Day5) => 5,
+        Some(IsaLevel::Day9) => 9,
+    }
+}
+
+fn level_from_number(number: u64) -> io::Result<Option<IsaLevel>> {
+    match number {
+        0 => Ok(None),
+        2 => Ok(Some(IsaLevel::Day2)),
+        5 => Ok(Some(IsaLevel::Day5)),
+        9 => Ok(Some(IsaLevel::Day9)),
+        _ => Err(invalid_data(format!(
+            "unknown instruction set level {}",
+            number
+        ))),
+    }
+}
+
+fn write_values<'a, W, T, I>(writer: &mut W, count: usize, values: I) -> io::Result<()>
+where
+    W: Write,
+    T: Register,
+    I: IntoIterator<Item = &'a T>,
+{
+    write_unsigned(writer, count as u64)?;
+
+    for value in values {
+        write_register(writer, value)?;
+    }
+
+    Ok(())
+}
+
+fn read_values<R: Read, T: Register>(reader: &mut R) -> io::Result<Vec<T>> {
+    let count = read_required(reader)?;
+
+    (0..count).map(|_| read_register(reader)).collect()
+}
+
+// Anything that doesn't fit in a usize can't be a real address, so the file must be corrupt
+fn read_size<R: Read>(reader: &mut R) -> io::Result<usize> {
+    let value = read_required(reader)?;
+    usize::try_from(value).map_err(|_| invalid_data(format!("{} is out of range", value)))
+}
+
+impl<T: Register> Snapshot<T> {
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(SNAPSHOT_MAGIC)?;
+        writer.write_all(&[SNAPSHOT_VERSION])?;
+
+        write_unsigned(writer, policy_number(self.arithmetic))?;
+        write_unsigned(writer, level_number(self.isa_level))?;
+        write_unsigned(writer, self.instruction_pointer as u64)?;
+        write_register(writer, &self.relative_offset)?;
+        write_unsigned(writer, self.running as u64)?;
+        write_values(writer, self.inputs.len(), &self.inputs)?;
+        write_values(writer, self.outputs.len(), &self.outputs)?;
+
+        let pages = self.memory.pages();
+
+        write_unsigned(writer, self.memory.high_water_mark() as u64)?;
+        write_unsigned(writer, pages.len() as u64)?;
+
+        for (page_number, values) in pages {
+            write_unsigned(writer, page_number as u64)?;
+
+            for value in values {
+                write_register(writer, value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Snapshot<T>> {
+        let mut header = [0u8; 5];
+        reader.read_exact(&mut header)?;
+
+        if &header[..4] != SNAPSHOT_MAGIC {
+            return Err(invalid_data("not an intcode snapshot".to_string()));
+        }
+
+        if header[4] != SNAPSHOT_VERSION {
+            return Err(invalid_data(format!(
+                "unsupported snapshot version {}",
+                header[4]
+            )));
+        }
+
+        let arithmetic = policy_from_number(read_required(reader)?)?;
+        let isa_level = level_from_number(read_required(reader)?)?;
+        let instruction_pointer = read_size(reader)?;
+        let relative_offset = read_register(reader)?;
+        let running = read_required(reader)? != 0;
+        let inputs = read_values(reader)?;
+        let outputs = read_values(reader)?.into_iter().collect();
+
+        let high_water_mark = read_size(reader)?;
+        let mut pages = Vec::new();
+
+        for _ in 0..read_required(reader)? {
+            let page_number = read_size(reader)?;
+            let values = (0..PAGE_SIZE)
+                .map(|_| read_register(reader))
+                .collect::<io::Result<Vec<T>>>()?;
+
+            pages.push((page_number, values));
+        }
+
+        let memory = Memory::from_pages(high_water_mark, &pages)
+            .ok_or_else(|| invalid_data("page number out of range".to_string()))?;
+
+        Ok(Snapshot {
+            arithmetic,
+            isa_level,
+            memory,
+            instruction_pointer,
+            relative_offset,
+            inputs,
+            outputs,
+            running,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Snapshot<T>> {
+        Snapshot::read_from(&mut BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::{IntCodeInterpreter, RunState};
+    use num::BigInt;
+
+    // Adds pairs of inputs together forever, keeping a running total at 1000
+    fn adder() -> IntCodeInterpreter {
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(&[
+            3, 100, 3, 101, 1, 100, 101, 102, 1, 102, 1000, 1000, 4, 1000, 1105, 1, 0,
+        ]);
+        interpreter
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let mut interpreter = adder();
+        interpreter.set_inputs(&[1, 2, 3]);
+        assert_eq!(interpreter.run(), Ok(RunState::AwaitingInput));
+
+        let snapshot = interpreter.snapshot();
+
+        interpreter.add_input(4);
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.last_output(), Some(10));
+
+        interpreter.restore(&snapshot);
+        assert!(interpreter.pending_inputs().is_empty());
+        assert_eq!(interpreter.outputs(), &[3]);
+
+        interpreter.add_input(-10);
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.last_output(), Some(-4));
+    }
+
+    #[test]
+    fn test_file_format_round_trip() {
+        let mut interpreter = adder();
+        interpreter.set_inputs(&[-5, 20, 7]);
+        interpreter.run().unwrap();
+        interpreter.add_input(9);
+
+        let snapshot = interpreter.snapshot();
+        let mut bytes = Vec::new();
+        snapshot.write_to(&mut bytes).unwrap();
+        assert_eq!(&bytes[..5], b"ICSN\x02");
+
+        let loaded = Snapshot::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.memory.to_vec(), snapshot.memory.to_vec());
+        assert_eq!(loaded.instruction_pointer, 2);
+        assert_eq!(loaded.relative_offset, snapshot.relative_offset);
+        assert_eq!(loaded.inputs, vec![9]);
+        assert_eq!(loaded.outputs, snapshot.outputs);
+        assert!(loaded.running);
+
+        let mut resumed = IntCodeInterpreter::new();
+        resumed.restore(&loaded);
+        resumed.run().unwrap();
+        assert_eq!(resumed.drain_outputs(), vec![15, 31]);
+    }
+
+    #[test]
+    fn test_machine_settings_are_kept() {
+        let mut interpreter = adder();
+        interpreter.set_arithmetic(ArithmeticPolicy::Wrapping);
+        interpreter.set_isa_level(IsaLevel::Day5);
+
+        let mut bytes = Vec::new();
+        interpreter.snapshot().write_to(&mut bytes).unwrap();
+
+        let loaded = Snapshot::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.arithmetic, ArithmeticPolicy::Wrapping);
+        assert_eq!(loaded.isa_level, Some(IsaLevel::Day5));
+
+        let mut resumed = IntCodeInterpreter::new();
+        resumed.restore(&loaded);
+        assert_eq!(resumed.arithmetic(), ArithmeticPolicy::Wrapping);
+        assert_eq!(resumed.instruction_set().level(), Some(IsaLevel::Day5));
+    }
+
+    #[test]
+    fn test_rejects_bad_files() {
+        assert!(Snapshot::<RegisterSize>::read_from(&mut &b"ICTR\x01"[..]).is_err());
+        assert!(Snapshot::<RegisterSize>::read_from(&mut &b"ICSN\x01"[..]).is_err());
+
+        let mut bytes = Vec::new();
+        adder().snapshot().write_to(&mut bytes).unwrap();
+        bytes.pop();
+        assert!(Snapshot::<RegisterSize>::read_from(&mut bytes.as_slice()).is_err());
+
+        // A page so far out that its first address doesn't fit
+        let mut bytes = b"ICSN\x02".to_vec();
+        for value in &[1, 9, 0, 0, 0, 0, 0, 0, 1, u64::MAX / 2] {
+            write_unsigned(&mut bytes, *value).unwrap();
+        }
+        bytes.extend(vec![0; PAGE_SIZE]);
+
+        let error = Snapshot::<RegisterSize>::read_from(&mut bytes.as_slice())
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "page number out of range");
+    }
+
+    #[test]
+    fn test_wide_snapshots() {
+        let mut interpreter: IntCodeInterpreter<BigInt> = IntCodeInterpreter::default();
+        interpreter.reset(&[
+            BigInt::from(2),
+            BigInt::from(5),
+            BigInt::from(5),
+            BigInt::from(5),
+            BigInt::from(99),
+            BigInt::from(1) << 40,
+        ]);
+        interpreter.run().unwrap();
+
+        let mut bytes = Vec::new();
+        interpreter.snapshot().write_to(&mut bytes).unwrap();
+
+        let loaded = Snapshot::<BigInt>::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.memory.to_vec()[5], BigInt::from(1) << 80);
+
+        // Too wide to be read back as i64
+        let error = Snapshot::<RegisterSize>::read_from(&mut bytes.as_slice())
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
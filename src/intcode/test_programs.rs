@@ -0,0 +1,10 @@
+// Small programs shared by the tests of more than one module
+use super::RegisterSize;
+
+// Reads a value, outputs it plus one, forever
+pub(crate) const INCREMENT: [RegisterSize; 12] = [3, 11, 101, 1, 11, 11, 4, 11, 1105, 1, 0, 0];
+
+// Passes on one less than whatever it's sent, halting once it sees or sends a zero
+pub(crate) const COUNTDOWN: [RegisterSize; 16] = [
+    3, 15, 1006, 15, 14, 1001, 15, -1, 15, 4, 15, 1005, 15, 0, 99, 0,
+];
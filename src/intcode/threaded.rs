@@ -0,0 +1,282 @@
+use super::limits::Progress;
+use super::{
+    InputSource, IntCodeInterpreter, IntcodeError, Limit, LoopInfo, OutputSink, RegisterSize,
+    StepResult,
+};
+use std::io;
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// How long a blocked read waits before checking whether it's been cancelled
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// How many instructions run between cancellation checks when the machine isn't reading
+const CANCEL_CHECK_INSTRUCTIONS: usize = 4096;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ThreadExit {
+    Halted,
+    Cancelled,
+    // The machine wanted input but every sender had hung up
+    InputClosed,
+    // From the interpreter's limits and loop detection, counted over the thread's whole run
+    LimitExceeded(Limit),
+    LoopDetected(LoopInfo),
+}
+
+pub struct FinishedMachine {
+    pub interpreter: IntCodeInterpreter,
+    pub exit: ThreadExit,
+}
+
+struct CancellableInput {
+    receiver: Receiver<RegisterSize>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl InputSource for CancellableInput {
+    fn read_input(&mut self) -> io::Result<Option<RegisterSize>> {
+        while !self.cancelled.load(Ordering::Relaxed) {
+            match self.receiver.recv_timeout(CANCEL_POLL_INTERVAL) {
+                Ok(value) => return Ok(Some(value)),
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return Ok(None),
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+// Machines downstream may well have halted first, as in a feedback loop, so anything sent after
+// they've gone is dropped rather than treated as an error
+struct DetachedOutput {
+    sender: Sender<RegisterSize>,
+}
+
+impl OutputSink for DetachedOutput {
+    fn write_output(&mut self, value: RegisterSize) -> io::Result<()> {
+        let _ = self.sender.send(value);
+        Ok(())
+    }
+}
+
+pub struct MachineHandle {
+    cancelled: Arc<AtomicBool>,
+    thread: JoinHandle<Result<FinishedMachine, IntcodeError>>,
+}
+
+impl MachineHandle {
+    // Stops the machine at its next read or within a few thousand instructions
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    // Hands back the interpreter as it was when the thread stopped, minus its channels. Its
+    // outputs have all gone down the channel, so its output queue is empty
+    pub fn join(self) -> Result<FinishedMachine, IntcodeError> {
+        match self.thread.join() {
+            Ok(result) => result,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+fn _run_machine(
+    interpreter: &mut IntCodeInterpreter,
+    cancelled: &AtomicBool,
+) -> Result<ThreadExit, IntcodeError> {
+    let mut progress = Progress::new();
+    let mut instructions = 0;
+
+    loop {
+        let result = interpreter.run_until_with(&mut progress, |result| {
+            instructions += 1;
+
+            matches!(result, StepResult::Output(_))
+                || (instructions % CANCEL_CHECK_INSTRUCTIONS == 0
+                    && cancelled.load(Ordering::Relaxed))
+        })?;
+
+        match result {
+            StepResult::Halted => return Ok(ThreadExit::Halted),
+            StepResult::NeedsInput if cancelled.load(Ordering::Relaxed) => {
+                return Ok(ThreadExit::Cancelled)
+            }
+            StepResult::NeedsInput => return Ok(ThreadExit::InputClosed),
+            StepResult::LimitExceeded(limit) => return Ok(ThreadExit::LimitExceeded(limit)),
+            StepResult::LoopDetected(info) => return Ok(ThreadExit::LoopDetected(info)),
+            // It's already gone down the channel, so there's no need to keep it as well
+            StepResult::Output(_) => interpreter.clear_output(),
+            // Only stops on anything else when it's been cancelled
+            StepResult::Continue => return Ok(ThreadExit::Cancelled),
+        }
+    }
+}
+
+// Runs the machine on its own thread, reading from and writing to the given channels. Any input
+// source or output sink it already had is replaced
+pub fn spawn_machine(
+    mut interpreter: IntCodeInterpreter,
+    input: Receiver<RegisterSize>,
+    output: Sender<RegisterSize>,
+) -> MachineHandle {
+    let cancelled = Arc::new(AtomicBool::new(false));
+
+    interpreter.set_input_source(CancellableInput {
+        receiver: input,
+        cancelled: cancelled.clone(),
+    });
+    interpreter.set_output_sink(DetachedOutput { sender: output });
+
+    let thread_cancelled = cancelled.clone();
+
+    let thread = thread::spawn(move || {
+        let result = _run_machine(&mut interpreter, &thread_cancelled);
+
+        // Drop the channels here, so machines downstream see this one has finished
+        interpreter.clear_input_source();
+        interpreter.clear_output_sink();
+
+        result.map(|exit| FinishedMachine { interpreter, exit })
+    });
+
+    MachineHandle { cancelled, thread }
+}
+
+// Like spawn_machine, but makes the channels, handing back their other ends
+pub fn spawn(
+    interpreter: IntCodeInterpreter,
+) -> (Sender<RegisterSize>, Receiver<RegisterSize>, MachineHandle) {
+    let (input_sender, input_receiver) = channel();
+    let (output_sender, output_receiver) = channel();
+
+    let handle = spawn_machine(interpreter, input_receiver, output_sender);
+
+    (input_sender, output_receiver, handle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::test_programs::{COUNTDOWN, INCREMENT};
+    use crate::intcode::Limits;
+
+    fn machine(program: &[RegisterSize]) -> IntCodeInterpreter {
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.reset(program);
+        interpreter
+    }
+
+    #[test]
+    fn test_spawn_and_join() {
+        let (input, output, handle) = spawn(machine(&INCREMENT));
+
+        input.send(1).unwrap();
+        assert_eq!(output.recv().unwrap(), 2);
+        input.send(41).unwrap();
+        assert_eq!(output.recv().unwrap(), 42);
+
+        drop(input);
+
+        let finished = handle.join().unwrap();
+        assert_eq!(finished.exit, ThreadExit::InputClosed);
+        assert!(finished.interpreter.outputs().is_empty());
+        assert!(output.recv().is_err());
+    }
+
+    #[test]
+    fn test_ring_of_threads() {
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..3).map(|_| channel()).unzip();
+        let first = senders[0].clone();
+
+        let handles = receivers
+            .into_iter()
+            .zip(senders.into_iter().cycle().skip(1))
+            .map(|(input, output)| spawn_machine(machine(&COUNTDOWN), input, output))
+            .collect::<Vec<MachineHandle>>();
+
+        first.send(10).unwrap();
+        drop(first);
+
+        let finished = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<FinishedMachine>>();
+
+        // The first two halt on their zeroes, which leaves the third waiting on a closed channel
+        assert_eq!(
+            finished
+                .iter()
+                .map(|machine| machine.exit)
+                .collect::<Vec<ThreadExit>>(),
+            vec![
+                ThreadExit::Halted,
+                ThreadExit::Halted,
+                ThreadExit::InputClosed
+            ]
+        );
+        assert!(finished
+            .iter()
+            .all(|machine| machine.interpreter.outputs().is_empty()));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let (input, _output, blocked) = spawn(machine(&INCREMENT));
+        let (_, _, spinning) = spawn(machine(&[1105, 1, 0]));
+
+        blocked.cancel();
+        spinning.cancel();
+
+        assert_eq!(blocked.join().unwrap().exit, ThreadExit::Cancelled);
+        assert_eq!(spinning.join().unwrap().exit, ThreadExit::Cancelled);
+        drop(input);
+    }
+
+    #[test]
+    fn test_limits_and_loops() {
+        let mut limited = machine(&INCREMENT);
+        limited.set_limits(Limits::new().outputs(2));
+        let (input, _output, limited) = spawn(limited);
+
+        for value in 0..3 {
+            input.send(value).unwrap();
+        }
+
+        let mut looping = machine(&[1105, 1, 0]);
+        looping.set_loop_detection(true);
+        let (_, _, looping) = spawn(looping);
+
+        // Stopping for each output doesn't start the count again
+        assert_eq!(
+            limited.join().unwrap().exit,
+            ThreadExit::LimitExceeded(Limit::Outputs(2))
+        );
+        assert!(matches!(
+            looping.join().unwrap().exit,
+            ThreadExit::LoopDetected(LoopInfo { start: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_errors_are_returned() {
+        let (_, _, handle) = spawn(machine(&[42]));
+
+        assert_eq!(
+            handle.join().err(),
+            Some(IntcodeError::UnknownOpcode {
+                instruction_pointer: 0,
+                opcode: 42,
+            })
+        );
+    }
+}
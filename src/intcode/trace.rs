@@ -0,0 +1,674 @@
+use super::varint::{
+    read_register, read_required, read_signed, read_unsigned, write_register, write_signed,
+    write_unsigned,
+};
+use super::{ParameterMode, Register, RegisterSize};
+use std::fmt::{self, Write as FmtWrite};
+use std::io::{self, BufRead, Read, Write};
+use std::marker::PhantomData;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+const BINARY_MAGIC: &[u8; 4] = b"ICTR";
+const BINARY_VERSION: u8 = 1;
+
+// Values are written out at full width, as plain JSON numbers or as longer varints
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TraceEvent<T = RegisterSize> {
+    pub instruction_pointer: usize,
+    pub opcode: RegisterSize,
+    pub modes: Vec<ParameterMode>,
+    // Values read for input parameters, and the resolved address for the written parameter
+    pub operands: Vec<T>,
+    pub writes: Vec<(usize, T)>,
+    // The new relative base, only present when the instruction changed it
+    pub relative_offset: Option<T>,
+    pub input: Option<T>,
+    pub output: Option<T>,
+}
+
+pub trait Tracer<T = RegisterSize> {
+    fn record(&mut self, event: &TraceEvent<T>) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn mode_number(mode: ParameterMode) -> u8 {
+    match mode {
+        ParameterMode::PositionMode => 0,
+        ParameterMode::ImmediateMode => 1,
+        ParameterMode::RelativeMode => 2,
+    }
+}
+
+fn mode_from_number(number: RegisterSize) -> io::Result<ParameterMode> {
+    match number {
+        0 => Ok(ParameterMode::PositionMode),
+        1 => Ok(ParameterMode::ImmediateMode),
+        2 => Ok(ParameterMode::RelativeMode),
+        _ => Err(invalid_data(format!("unknown parameter mode {}", number))),
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Keeps every event in memory, shared so the caller can look at them while the machine runs
+#[derive(Clone, Default)]
+pub struct TraceRecorder<T = RegisterSize> {
+    events: Arc<Mutex<Vec<TraceEvent<T>>>>,
+}
+
+impl TraceRecorder {
+    pub fn new() -> Self {
+        TraceRecorder::default()
+    }
+}
+
+impl<T: Clone> TraceRecorder<T> {
+    pub fn events(&self) -> Vec<TraceEvent<T>> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+impl<T: Clone> Tracer<T> for TraceRecorder<T> {
+    fn record(&mut self, event: &TraceEvent<T>) -> io::Result<()> {
+        self.events.lock().unwrap().push(event.clone());
+        Ok(())
+    }
+}
+
+fn json_option<T: fmt::Display>(value: &Option<T>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "null".to_string(),
+    }
+}
+
+fn json_list<T, F: Fn(&T) -> String>(values: &[T], format: F) -> String {
+    format!(
+        "[{}]",
+        values.iter().map(format).collect::<Vec<String>>().join(",")
+    )
+}
+
+pub fn to_json<T: Register>(event: &TraceEvent<T>) -> String {
+    let mut json = String::new();
+
+    write!(
+        json,
+        "{{\"ip\":{},\"opcode\":{},\"modes\":{},\"operands\":{},\"writes\":{},\"rb\":{},\"input\":{},\"output\":{}}}",
+        event.instruction_pointer,
+        event.opcode,
+        json_list(&event.modes, |mode| mode_number(*mode).to_string()),
+        json_list(&event.operands, |value| value.to_string()),
+        json_list(&event.writes, |(address, value)| format!("[{},{}]", address, value)),
+        json_option(&event.relative_offset),
+        json_option(&event.input),
+        json_option(&event.output),
+    )
+    .unwrap();
+
+    json
+}
+
+// Just enough JSON to read back what to_json writes, plus whatever whitespace tools add. Numbers
+// are kept as text until it's known what width they should be
+#[derive(Debug, Clone, PartialEq)]
+enum JsonValue {
+    Null,
+    Number(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+struct JsonParser<'a> {
+    text: &'a [u8],
+    position: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn error(&self, message: &str) -> io::Error {
+        invalid_data(format!("{} at column {}", message, self.position + 1))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.position < self.text.len() && self.text[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: u8) -> io::Result<()> {
+        self.skip_whitespace();
+
+        if self.text.get(self.position) == Some(&expected) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", expected as char)))
+        }
+    }
+
+    // Lists of values or key/value pairs, separated by commas
+    fn parse_sequence<T, F>(&mut self, close: u8, mut parse_item: F) -> io::Result<Vec<T>>
+    where
+        F: FnMut(&mut Self) -> io::Result<T>,
+    {
+        let mut items = Vec::new();
+        self.skip_whitespace();
+
+        if self.text.get(self.position) == Some(&close) {
+            self.position += 1;
+            return Ok(items);
+        }
+
+        loop {
+            items.push(parse_item(self)?);
+            self.skip_whitespace();
+
+            match self.text.get(self.position) {
+                Some(b',') => self.position += 1,
+                Some(c) if *c == close => {
+                    self.position += 1;
+                    return Ok(items);
+                }
+                _ => return Err(self.error("expected ',' or end of sequence")),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> io::Result<String> {
+        self.expect(b'"')?;
+        let start = self.position;
+
+        while self.position < self.text.len() && self.text[self.position] != b'"' {
+            self.position += 1;
+        }
+
+        let value = String::from_utf8_lossy(&self.text[start..self.position]).to_string();
+        self.expect(b'"')?;
+
+        Ok(value)
+    }
+
+    fn parse_value(&mut self) -> io::Result<JsonValue> {
+        self.skip_whitespace();
+
+        match self.text.get(self.position) {
+            Some(b'[') => {
+                self.position += 1;
+                Ok(JsonValue::Array(
+                    self.parse_sequence(b']', |parser| parser.parse_value())?,
+                ))
+            }
+            Some(b'{') => {
+                self.position += 1;
+                Ok(JsonValue::Object(self.parse_sequence(b'}', |parser| {
+                    let key = parser.parse_string()?;
+                    parser.expect(b':')?;
+                    Ok((key, parser.parse_value()?))
+                })?))
+            }
+            Some(b'n') if self.text[self.position..].starts_with(b"null") => {
+                self.position += 4;
+                Ok(JsonValue::Null)
+            }
+            Some(c) if *c == b'-' || c.is_ascii_digit() => {
+                let start = self.position;
+                self.position += 1;
+
+                while self.position < self.text.len() && self.text[self.position].is_ascii_digit() {
+                    self.position += 1;
+                }
+
+                if self.position - start == 1 && *c == b'-' {
+                    return Err(self.error("invalid number"));
+                }
+
+                Ok(JsonValue::Number(
+                    String::from_utf8_lossy(&self.text[start..self.position]).to_string(),
+                ))
+            }
+            _ => Err(self.error("unexpected character")),
+        }
+    }
+}
+
+fn json_number<T: FromStr>(value: &JsonValue) -> io::Result<T> {
+    match value {
+        JsonValue::Number(number) => number
+            .parse()
+            .map_err(|_| invalid_data(format!("{} is out of range", number))),
+        _ => Err(invalid_data("expected a number".to_string())),
+    }
+}
+
+fn json_numbers<T: FromStr>(value: &JsonValue) -> io::Result<Vec<T>> {
+    match value {
+        JsonValue::Array(values) => values.iter().map(json_number).collect(),
+        _ => Err(invalid_data("expected an array".to_string())),
+    }
+}
+
+fn json_optional_number<T: FromStr>(value: &JsonValue) -> io::Result<Option<T>> {
+    match value {
+        JsonValue::Null => Ok(None),
+        _ => json_number(value).map(Some),
+    }
+}
+
+pub fn from_json<T: Register>(line: &str) -> io::Result<TraceEvent<T>> {
+    let mut parser = JsonParser {
+        text: line.as_bytes(),
+        position: 0,
+    };
+
+    let fields = match parser.parse_value()? {
+        JsonValue::Object(fields) => fields,
+        _ => return Err(invalid_data("expected an object".to_string())),
+    };
+
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+            .ok_or_else(|| invalid_data(format!("missing field {:?}", name)))
+    };
+
+    let writes = match field("writes")? {
+        JsonValue::Array(writes) => writes
+            .iter()
+            .map(|write| match write {
+                JsonValue::Array(pair) if pair.len() == 2 => {
+                    Ok((json_number(&pair[0])?, json_number(&pair[1])?))
+                }
+                _ => Err(invalid_data(
+                    "expected an [address, value] pair".to_string(),
+                )),
+            })
+            .collect::<io::Result<Vec<(usize, T)>>>()?,
+        _ => return Err(invalid_data("expected an array of writes".to_string())),
+    };
+
+    Ok(TraceEvent {
+        instruction_pointer: json_number(field("ip")?)?,
+        opcode: json_number(field("opcode")?)?,
+        modes: json_numbers::<RegisterSize>(field("modes")?)?
+            .into_iter()
+            .map(mode_from_number)
+            .collect::<io::Result<Vec<ParameterMode>>>()?,
+        operands: json_numbers(field("operands")?)?,
+        writes,
+        relative_offset: json_optional_number(field("rb")?)?,
+        input: json_optional_number(field("input")?)?,
+        output: json_optional_number(field("output")?)?,
+    })
+}
+
+pub struct JsonLinesWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonLinesWriter<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesWriter { writer }
+    }
+}
+
+impl<W: Write, T: Register> Tracer<T> for JsonLinesWriter<W> {
+    fn record(&mut self, event: &TraceEvent<T>) -> io::Result<()> {
+        writeln!(self.writer, "{}", to_json(event))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+pub struct JsonLinesReader<R: BufRead, T = RegisterSize> {
+    lines: io::Lines<R>,
+    register: PhantomData<T>,
+}
+
+impl<R: BufRead, T: Register> JsonLinesReader<R, T> {
+    pub fn new(reader: R) -> Self {
+        JsonLinesReader {
+            lines: reader.lines(),
+            register: PhantomData,
+        }
+    }
+}
+
+impl<R: BufRead, T: Register> Iterator for JsonLinesReader<R, T> {
+    type Item = io::Result<TraceEvent<T>>;
+
+    fn next(&mut self) -> Option<io::Result<TraceEvent<T>>> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(error) => return Some(Err(error)),
+            };
+
+            if !line.trim().is_empty() {
+                return Some(from_json(&line));
+            }
+        }
+    }
+}
+
+const HAS_RELATIVE_OFFSET: u64 = 1;
+const HAS_INPUT: u64 = 2;
+const HAS_OUTPUT: u64 = 4;
+
+pub struct BinaryTraceWriter<W: Write> {
+    writer: W,
+    wrote_header: bool,
+}
+
+impl<W: Write> BinaryTraceWriter<W> {
+    pub fn new(writer: W) -> Self {
+        BinaryTraceWriter {
+            writer,
+            wrote_header: false,
+        }
+    }
+
+    fn _write_header(&mut self) -> io::Result<()> {
+        if !self.wrote_header {
+            self.writer.write_all(BINARY_MAGIC)?;
+            self.writer.write_all(&[BINARY_VERSION])?;
+            self.wrote_header = true;
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Write, T: Register> Tracer<T> for BinaryTraceWriter<W> {
+    fn record(&mut self, event: &TraceEvent<T>) -> io::Result<()> {
+        self._write_header()?;
+
+        let writer = &mut self.writer;
+
+        write_unsigned(writer, event.instruction_pointer as u64)?;
+        write_signed(writer, event.opcode)?;
+
+        // Modes are packed two bits apiece, which is plenty for three operands
+        write_unsigned(writer, event.operands.len() as u64)?;
+        write_unsigned(
+            writer,
+            event
+                .modes
+                .iter()
+                .rev()
+                .fold(0, |packed, mode| (packed << 2) | mode_number(*mode) as u64),
+        )?;
+
+        for operand in &event.operands {
+            write_register(writer, operand)?;
+        }
+
+        write_unsigned(writer, event.writes.len() as u64)?;
+
+        for (address, value) in &event.writes {
+            write_unsigned(writer, *address as u64)?;
+            write_register(writer, value)?;
+        }
+
+        let flags = [
+            (&event.relative_offset, HAS_RELATIVE_OFFSET),
+            (&event.input, HAS_INPUT),
+            (&event.output, HAS_OUTPUT),
+        ];
+
+        write_unsigned(
+            writer,
+            flags
+                .iter()
+                .filter(|(value, _)| value.is_some())
+                .fold(0, |packed, (_, flag)| packed | flag),
+        )?;
+
+        for (value, _) in flags.iter() {
+            if let Some(value) = value {
+                write_register(writer, value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // A run that recorded nothing still leaves a readable, empty trace
+    fn flush(&mut self) -> io::Result<()> {
+        self._write_header()?;
+        self.writer.flush()
+    }
+}
+
+pub struct BinaryTraceReader<R: Read, T = RegisterSize> {
+    reader: R,
+    register: PhantomData<T>,
+}
+
+impl<R: Read, T: Register> BinaryTraceReader<R, T> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; 5];
+        reader.read_exact(&mut header)?;
+
+        if &header[..4] != BINARY_MAGIC {
+            return Err(invalid_data("not an intcode trace".to_string()));
+        }
+
+        if header[4] != BINARY_VERSION {
+            return Err(invalid_data(format!(
+                "unsupported trace version {}",
+                header[4]
+            )));
+        }
+
+        Ok(BinaryTraceReader {
+            reader,
+            register: PhantomData,
+        })
+    }
+
+    fn read_event(&mut self) -> io::Result<Option<TraceEvent<T>>> {
+        let reader = &mut self.reader;
+
+        let instruction_pointer = match read_unsigned(reader)? {
+            Some(instruction_pointer) => instruction_pointer as usize,
+            None => return Ok(None),
+        };
+
+        let opcode = read_signed(reader)?;
+        let operand_count = read_required(reader)?;
+        let mut packed_modes = read_required(reader)?;
+
+        let mut modes = Vec::new();
+        let mut operands = Vec::new();
+
+        for _ in 0..operand_count {
+            modes.push(mode_from_number((packed_modes & 3) as RegisterSize)?);
+            packed_modes >>= 2;
+            operands.push(read_register(reader)?);
+        }
+
+        let mut writes = Vec::new();
+
+        for _ in 0..read_required(reader)? {
+            writes.push((read_required(reader)? as usize, read_register(reader)?));
+        }
+
+        let flags = read_required(reader)?;
+        let mut optional = |flag: u64| -> io::Result<Option<T>> {
+            if flags & flag != 0 {
+                read_register(reader).map(Some)
+            } else {
+                Ok(None)
+            }
+        };
+
+        Ok(Some(TraceEvent {
+            instruction_pointer,
+            opcode,
+            modes,
+            operands,
+            writes,
+            relative_offset: optional(HAS_RELATIVE_OFFSET)?,
+            input: optional(HAS_INPUT)?,
+            output: optional(HAS_OUTPUT)?,
+        }))
+    }
+}
+
+impl<R: Read, T: Register> Iterator for BinaryTraceReader<R, T> {
+    type Item = io::Result<TraceEvent<T>>;
+
+    fn next(&mut self) -> Option<io::Result<TraceEvent<T>>> {
+        self.read_event().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::intcode::IntCodeInterpreter;
+
+    fn sample_events() -> Vec<TraceEvent> {
+        let recorder = TraceRecorder::new();
+
+        let mut interpreter = IntCodeInterpreter::new();
+        interpreter.set_tracer(recorder.clone());
+        interpreter.reset(&[109, -3, 203, 16, 1002, 13, -2, 14, 204, 17, 99, 0, 0, 0, 0]);
+        interpreter.add_input(-35000);
+        interpreter.run().unwrap();
+
+        recorder.events()
+    }
+
+    #[test]
+    fn test_recorded_events() {
+        let events = sample_events();
+
+        assert_eq!(events.len(), 5);
+        assert_eq!(events[0].relative_offset, Some(-3));
+        assert_eq!(
+            events[1],
+            TraceEvent {
+                instruction_pointer: 2,
+                opcode: 203,
+                modes: vec![ParameterMode::RelativeMode],
+                operands: vec![13],
+                writes: vec![(13, -35000)],
+                relative_offset: None,
+                input: Some(-35000),
+                output: None,
+            }
+        );
+        assert_eq!(events[2].operands, vec![-35000, -2, 14]);
+        assert_eq!(events[2].writes, vec![(14, 70000)]);
+        assert_eq!(events[3].output, Some(70000));
+        assert_eq!(events[4].opcode, 99);
+    }
+
+    #[test]
+    fn test_json_lines_round_trip() {
+        let events = sample_events();
+        let mut writer = JsonLinesWriter::new(Vec::new());
+
+        for event in &events {
+            writer.record(event).unwrap();
+        }
+
+        let text = String::from_utf8(writer.writer).unwrap();
+        assert!(text.starts_with(
+            "{\"ip\":0,\"opcode\":109,\"modes\":[1],\"operands\":[-3],\"writes\":[],\"rb\":-3,\"input\":null,\"output\":null}\n"
+        ));
+
+        let read_back = JsonLinesReader::new(text.as_bytes())
+            .collect::<io::Result<Vec<TraceEvent>>>()
+            .unwrap();
+        assert_eq!(read_back, events);
+    }
+
+    #[test]
+    fn test_json_tolerates_whitespace() {
+        let event: TraceEvent = from_json(
+            "{ \"ip\": 4, \"opcode\": 4, \"modes\": [0], \"operands\": [ 7 ], \"writes\": [],\
+             \"rb\": null, \"input\": null, \"output\": 7 }",
+        )
+        .unwrap();
+
+        assert_eq!(event.output, Some(7));
+        assert!(from_json::<RegisterSize>("{\"ip\": 4}").is_err());
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let events = sample_events();
+        let mut writer = BinaryTraceWriter::new(Vec::new());
+
+        for event in &events {
+            writer.record(event).unwrap();
+        }
+
+        let bytes = writer.writer;
+        let read_back = BinaryTraceReader::new(bytes.as_slice())
+            .unwrap()
+            .collect::<io::Result<Vec<TraceEvent>>>()
+            .unwrap();
+        assert_eq!(read_back, events);
+
+        assert!(BinaryTraceReader::<_, RegisterSize>::new(&b"JUNK\x01"[..]).is_err());
+        assert!(
+            BinaryTraceReader::<_, RegisterSize>::new(&bytes[..bytes.len() - 1])
+                .unwrap()
+                .any(|event| event.is_err())
+        );
+    }
+
+    #[test]
+    fn test_empty_binary_trace() {
+        let mut writer = BinaryTraceWriter::new(Vec::new());
+        Tracer::<RegisterSize>::flush(&mut writer).unwrap();
+
+        let bytes = writer.writer;
+        let mut reader = BinaryTraceReader::<_, RegisterSize>::new(bytes.as_slice()).unwrap();
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_wide_values() {
+        let event = TraceEvent {
+            instruction_pointer: 0,
+            opcode: 4,
+            modes: vec![ParameterMode::ImmediateMode],
+            operands: vec![-(1i128 << 100)],
+            writes: vec![],
+            relative_offset: None,
+            input: None,
+            output: Some(-(1i128 << 100)),
+        };
+
+        let json = to_json(&event);
+        assert_eq!(from_json::<i128>(&json).unwrap(), event);
+        assert_eq!(
+            from_json::<RegisterSize>(&json).unwrap_err().to_string(),
+            "-1267650600228229401496703205376 is out of range"
+        );
+
+        let mut writer = BinaryTraceWriter::new(Vec::new());
+        writer.record(&event).unwrap();
+
+        let bytes = writer.writer;
+        let mut reader = BinaryTraceReader::<_, i128>::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap(), event);
+
+        let mut narrow = BinaryTraceReader::<_, RegisterSize>::new(bytes.as_slice()).unwrap();
+        assert!(narrow.next().unwrap().is_err());
+    }
+}
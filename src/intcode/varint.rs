@@ -0,0 +1,167 @@
+use super::{Register, RegisterSize};
+use num::{BigInt, BigUint, ToPrimitive, Zero};
+use std::io::{self, Read, Write};
+
+// Unsigned LEB128, with zigzag encoding on top for signed values
+pub(crate) fn write_unsigned<W: Write>(writer: &mut W, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            return writer.write_all(&[byte]);
+        }
+
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+pub(crate) fn write_signed<W: Write>(writer: &mut W, value: RegisterSize) -> io::Result<()> {
+    write_unsigned(writer, ((value << 1) ^ (value >> 63)) as u64)
+}
+
+// Returns None on a clean end of file before the first byte
+pub(crate) fn read_unsigned<R: Read>(reader: &mut R) -> io::Result<Option<u64>> {
+    let mut value = 0u64;
+    let mut shift = 0;
+
+    loop {
+        let mut byte = [0u8];
+
+        if reader.read(&mut byte)? == 0 {
+            return if shift == 0 {
+                Ok(None)
+            } else {
+                Err(io::Error::from(io::ErrorKind::UnexpectedEof))
+            };
+        }
+
+        if shift >= 64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "varint too long",
+            ));
+        }
+
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        shift += 7;
+
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+}
+
+pub(crate) fn read_required<R: Read>(reader: &mut R) -> io::Result<u64> {
+    read_unsigned(reader)?.ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))
+}
+
+fn unzigzag(value: u64) -> RegisterSize {
+    ((value >> 1) as RegisterSize) ^ -((value & 1) as RegisterSize)
+}
+
+pub(crate) fn read_signed<R: Read>(reader: &mut R) -> io::Result<RegisterSize> {
+    read_required(reader).map(unzigzag)
+}
+
+// Zigzag works on the value rather than the width, so anything that fits in an i64 is written
+// exactly as write_signed would, whatever the register type
+pub(crate) fn write_register<W: Write, T: Register>(writer: &mut W, value: &T) -> io::Result<()> {
+    if let Some(value) = value.to_i64() {
+        return write_signed(writer, value);
+    }
+
+    let value = value.to_bigint();
+    let zigzag = if value < BigInt::zero() {
+        (-value << 1) - BigInt::from(1)
+    } else {
+        value << 1
+    };
+    let mut unsigned = zigzag.to_biguint().unwrap();
+
+    loop {
+        let byte = (&unsigned % BigUint::from(0x80u8)).to_u8().unwrap();
+        unsigned >>= 7;
+
+        if unsigned.is_zero() {
+            return writer.write_all(&[byte]);
+        }
+
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+pub(crate) fn read_register<R: Read, T: Register>(reader: &mut R) -> io::Result<T> {
+    let mut groups = Vec::new();
+
+    loop {
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+        groups.push(byte[0] & 0x7f);
+
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+    }
+
+    // Nine groups is 63 bits, which always fits
+    if groups.len() <= 9 {
+        let value = groups
+            .iter()
+            .rev()
+            .fold(0u64, |value, group| (value << 7) | *group as u64);
+
+        return Ok(T::from_i64(unzigzag(value)));
+    }
+
+    let unsigned = groups.iter().rev().fold(BigUint::zero(), |value, group| {
+        (value << 7) + BigUint::from(*group)
+    });
+    let magnitude = BigInt::from(unsigned >> 1);
+    let value = if groups[0] & 1 == 0 {
+        magnitude
+    } else {
+        -magnitude - BigInt::from(1)
+    };
+
+    T::from_bigint(&value).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is too wide for the register", value),
+        )
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip<T: Register>(value: T) -> io::Result<T> {
+        let mut bytes = Vec::new();
+        write_register(&mut bytes, &value)?;
+        read_register(&mut bytes.as_slice())
+    }
+
+    #[test]
+    fn test_wide_values() {
+        let mut bytes = Vec::new();
+        write_register(&mut bytes, &(-35000i128)).unwrap();
+        assert_eq!(read_signed(&mut bytes.as_slice()).unwrap(), -35000);
+
+        assert_eq!(round_trip(i64::MIN).unwrap(), i64::MIN);
+        assert_eq!(round_trip(i128::MAX).unwrap(), i128::MAX);
+        assert_eq!(round_trip(i128::MIN).unwrap(), i128::MIN);
+
+        let big = BigInt::from(-7) << 300;
+        assert_eq!(round_trip(big.clone()).unwrap(), big);
+
+        let mut bytes = Vec::new();
+        write_register(&mut bytes, &(1i128 << 64)).unwrap();
+        let error = read_register::<_, RegisterSize>(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "18446744073709551616 is too wide for the register"
+        );
+    }
+}